@@ -0,0 +1,169 @@
+use crate::github::Http;
+use crate::model::Entity;
+use crate::report::Failure;
+use crate::{log, markdown, utils};
+use anyhow::anyhow;
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Load the archive from a local file or download it if `src` is an url
+pub async fn load_archive(http: &Http, src: &str) -> anyhow::Result<Vec<u8>> {
+    if src.starts_with("http://") || src.starts_with("https://") {
+        return http.download(src).await;
+    }
+
+    std::fs::read(src).map_err(|e| anyhow!("can't read archive {}: {}", src, e))
+}
+
+/// Parse all tips from a `.tar.gz` or `.zip` archive of the tips repository
+///
+/// The archive is extracted in memory, GitHub archives wrap every file in a
+/// top level directory such as `laravel-tips-master/`, which does not matter
+/// since the group only depends on the file name. Files that can't be read are
+/// returned as failures, the other files are still parsed.
+pub fn parse_archive_tips(
+    bytes: &[u8],
+    quiet: bool,
+) -> anyhow::Result<(Vec<Entity>, Vec<Failure>)> {
+    let (mut files, failures) = if bytes.starts_with(GZIP_MAGIC) {
+        read_tar_gz(bytes)?
+    } else if bytes.starts_with(ZIP_MAGIC) {
+        read_zip(bytes)?
+    } else {
+        return Err(anyhow!(
+            "unknown archive format, only support [tar.gz, zip]"
+        ));
+    };
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut entities = Vec::new();
+    for (path, content) in files {
+        if !quiet {
+            log!(format!(" parsing file: {}", path));
+        }
+
+        let tips = markdown::parse_tips(&content);
+        entities.extend(utils::convert_tips_to_entities(tips, &path, ""));
+    }
+
+    Ok((entities, failures))
+}
+
+/// The path and content of every tips file of an archive, and the files failed to read
+type Files = (Vec<(String, String)>, Vec<Failure>);
+
+/// Read the content of an archive entry, it must be valid UTF-8
+fn read_content(mut reader: impl Read) -> anyhow::Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn read_tar_gz(bytes: &[u8]) -> anyhow::Result<Files> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    let mut files = Vec::new();
+    let mut failures = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+
+        if entry.header().entry_type().is_file() && utils::is_tips_file(&path) {
+            match read_content(&mut entry) {
+                Ok(content) => files.push((path, content)),
+                Err(err) => failures.push(Failure::new(&path, &err)),
+            }
+        }
+    }
+
+    Ok((files, failures))
+}
+
+fn read_zip(bytes: &[u8]) -> anyhow::Result<Files> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut files = Vec::new();
+    let mut failures = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let path = file.name().to_string();
+
+        if file.is_file() && utils::is_tips_file(&path) {
+            match read_content(&mut file) {
+                Ok(content) => files.push((path, content)),
+                Err(err) => failures.push(Failure::new(&path, &err)),
+            }
+        }
+    }
+
+    Ok((files, failures))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const FILES: &[(&str, &[u8])] = &[
+        ("laravel-tips-master/README.md", b"### Table of contents\n"),
+        (
+            "laravel-tips-master/arr.md",
+            b"# Arr\n\n### Arr::get\nget it\n",
+        ),
+        ("laravel-tips-master/broken.md", &[0xff, 0xfe, 0xfd]),
+        (
+            "laravel-tips-master/eloquent.md",
+            b"### First\nfirst\n\n### Second\nsecond\n",
+        ),
+    ];
+
+    fn tar_gz() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+
+        let tar = builder.into_inner().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&tar).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip() -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, content) in FILES {
+            writer
+                .start_file(*path, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_parse_archive_tips() {
+        for bytes in [tar_gz(), zip()] {
+            let (entities, failures) = parse_archive_tips(&bytes, true).unwrap();
+
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].path, "laravel-tips-master/broken.md");
+
+            assert_eq!(entities.len(), 3);
+            assert_eq!(entities[0].title, "Arr::get");
+            assert_eq!(entities[0].group, "arr");
+            assert_eq!(entities[2].title, "Second");
+            assert_eq!(entities[2].group, "eloquent");
+        }
+
+        assert!(parse_archive_tips(b"not an archive", true).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// An on-disk cache of http responses keyed by url
+///
+/// Each response is stored as two files named by the sha256 of the url, the
+/// body and its `ETag`, the etag is written last so a partial write is never
+/// taken as a hit.
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+/// A cached response body and its etag
+pub struct Cached {
+    pub etag: String,
+    pub body: Vec<u8>,
+}
+
+impl HttpCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, url: &str, ext: &str) -> PathBuf {
+        let key: String = Sha256::digest(url.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        self.dir.join(format!("{}.{}", key, ext))
+    }
+
+    /// Load the cached response of the url, `None` if not cached or broken
+    pub fn load(&self, url: &str) -> Option<Cached> {
+        let etag = std::fs::read_to_string(self.path(url, "etag")).ok()?;
+        let body = std::fs::read(self.path(url, "body")).ok()?;
+
+        Some(Cached { etag, body })
+    }
+
+    pub fn save(&self, url: &str, etag: &str, body: &[u8]) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        let _ = std::fs::remove_file(self.path(url, "etag"));
+        std::fs::write(self.path(url, "body"), body)?;
+        std::fs::write(self.path(url, "etag"), etag)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TestDir::new("http-cache");
+        let cache = HttpCache::new(dir.to_path_buf());
+        let url = "https://api.github.com/repos/LaravelDaily/laravel-tips/git/trees/master";
+        assert!(cache.load(url).is_none());
+
+        cache.save(url, "W/\"abc\"", b"{\"sha\":\"1\"}").unwrap();
+        let cached = cache.load(url).unwrap();
+
+        assert_eq!(cached.etag, "W/\"abc\"");
+        assert_eq!(cached.body, b"{\"sha\":\"1\"}");
+        assert!(cache.load("https://example.com").is_none());
+    }
+}
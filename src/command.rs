@@ -1,12 +1,25 @@
+use crate::cache::HttpCache;
+use crate::model::{Entity, SourceState};
 use crate::pretty::Pretty;
-use crate::storage::{new_storage, Storage};
-use crate::{github, Args, OutputFormat, SubCommands};
+use crate::report::{Diff, Failure, ParseReport, SourceReport, Stats, SyncReport};
+use crate::source::{self, Source, SourceKind, DEFAULT_SOURCE};
+use crate::storage::{new_storage, Filter, Page, Sort, Storage};
+use crate::utils::normalize_path;
+use crate::{github, markdown, utils, Args, OutputFormat, SubCommands};
+use anyhow::anyhow;
 use argh::FromArgs;
 use async_trait::async_trait;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const SOURCES_FILE: &str = "sources.json";
+const HTTP_CACHE_DIR: &str = "http-cache";
 
 struct Opts {
     format: OutputFormat,
     quiet: bool,
+    path: Option<PathBuf>,
 }
 
 #[async_trait]
@@ -30,12 +43,18 @@ impl Manager {
         let opt = Opts {
             format: self.args.output.clone().unwrap_or(OutputFormat::Terminal),
             quiet: self.args.quiet,
+            path: self.args.path.clone().map(PathBuf::from),
         };
 
         match com {
             SubCommands::Random(cmd) => cmd.execute(storage, &opt).await,
             SubCommands::Sync(cmd) => cmd.execute(storage, &opt).await,
             SubCommands::Search(cmd) => cmd.execute(storage, &opt).await,
+            SubCommands::Show(cmd) => cmd.execute(storage, &opt).await,
+            SubCommands::List(cmd) => cmd.execute(storage, &opt).await,
+            SubCommands::Groups(cmd) => cmd.execute(storage, &opt).await,
+            SubCommands::Stats(cmd) => cmd.execute(storage, &opt).await,
+            SubCommands::Parse(cmd) => cmd.execute(storage, &opt).await,
         }
     }
 }
@@ -43,37 +62,407 @@ impl Manager {
 #[derive(FromArgs, Debug)]
 #[argh(subcommand, name = "sync")]
 #[argh(description = "sync laravel tips from laravel docs")]
-pub struct SyncCommand {}
+pub struct SyncCommand {
+    #[argh(switch, short = 'f')]
+    #[argh(description = "re-download all files even if they are not changed since last sync")]
+    force: bool,
+
+    #[argh(switch)]
+    #[argh(description = "exit successfully even if some files failed to sync")]
+    allow_partial: bool,
+
+    #[argh(switch)]
+    #[argh(description = "show the added, removed and changed tips without writing anything")]
+    dry_run: bool,
+
+    #[argh(option, default = "8")]
+    #[argh(description = "the maximum number of in-flight requests, default is 8")]
+    concurrency: usize,
+
+    #[argh(option, default = "30")]
+    #[argh(description = "the timeout of each request in seconds, default is 30")]
+    timeout: u64,
+
+    #[argh(switch)]
+    #[argh(description = "do not use the on-disk http cache")]
+    no_cache: bool,
+
+    #[argh(option)]
+    #[argh(
+        description = "sync from a local directory of markdown files instead of github, such as a clone of LaravelDaily/laravel-tips"
+    )]
+    from: Option<String>,
+
+    #[argh(option)]
+    #[argh(
+        description = "sync from a .tar.gz or .zip archive of the tips repository, can be a local file or an url"
+    )]
+    archive: Option<String>,
+
+    #[argh(option, short = 'n')]
+    #[argh(
+        description = "the name of the source to sync, only sync this source if it is configured in sources.json, default is 'default'"
+    )]
+    name: Option<String>,
+
+    #[argh(option)]
+    #[argh(
+        description = "the github api base url, such as https://github.example.com/api/v3, env LARAVEL_TIPS_API_BASE_URL"
+    )]
+    api: Option<String>,
+
+    #[argh(option)]
+    #[argh(
+        description = "the owner of the tips repository, default is LaravelDaily, env LARAVEL_TIPS_REPO_OWNER"
+    )]
+    owner: Option<String>,
+
+    #[argh(option)]
+    #[argh(
+        description = "the name of the tips repository, default is laravel-tips, env LARAVEL_TIPS_REPO_NAME"
+    )]
+    repo: Option<String>,
+
+    #[argh(option, long = "ref")]
+    #[argh(
+        description = "the branch, tag or commit to sync, default is master, env LARAVEL_TIPS_REPO_REF"
+    )]
+    reference: Option<String>,
+
+    #[argh(option)]
+    #[argh(
+        description = "only sync files matching the glob, can be repeated, env LARAVEL_TIPS_INCLUDE"
+    )]
+    include: Vec<String>,
+
+    #[argh(option)]
+    #[argh(
+        description = "skip files matching the glob, can be repeated, env LARAVEL_TIPS_EXCLUDE"
+    )]
+    exclude: Vec<String>,
+}
 
 #[derive(FromArgs, Debug)]
 #[argh(subcommand, name = "random")]
 #[argh(description = "random laravel tips")]
-pub struct RandomCommand {}
+pub struct RandomCommand {
+    #[argh(option, short = 's')]
+    #[argh(description = "only pick tips from the source, such as 'default'")]
+    source: Option<String>,
+}
 
 #[derive(FromArgs, Debug, Clone)]
 #[argh(subcommand, name = "search")]
 #[argh(description = "search laravel tips by keyword")]
 pub struct SearchCommand {
     #[argh(positional)]
+    #[argh(
+        description = "the keyword to search, support FTS5 query syntax such as \"a phrase\", prefix* and AND/OR/NOT"
+    )]
     keyword: String,
 
     #[argh(option, short = 'g')]
     #[argh(description = "specify the group to search, such as 'eloquent', 'artisan', 'arr'")]
     group: Option<String>,
+
+    #[argh(option, short = 's')]
+    #[argh(description = "specify the source to search, such as 'default'")]
+    source: Option<String>,
+}
+
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "show")]
+#[argh(description = "show a laravel tip by id")]
+pub struct ShowCommand {
+    #[argh(positional)]
+    #[argh(description = "the id of the tip, printed by [random] and [search]")]
+    id: String,
+}
+
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "list")]
+#[argh(description = "list laravel tips page by page")]
+pub struct ListCommand {
+    #[argh(option, short = 'l', default = "20")]
+    #[argh(description = "the maximum number of tips to list, default is 20")]
+    limit: usize,
+
+    #[argh(option)]
+    #[argh(description = "skip the first n tips, can not be used with --page")]
+    offset: Option<usize>,
+
+    #[argh(option)]
+    #[argh(description = "the page to list starting from 1, each page has --limit tips")]
+    page: Option<usize>,
+
+    #[argh(option)]
+    #[argh(
+        description = "sort tips by [position, title, group, id], default is position, the order in the source files"
+    )]
+    sort: Option<Sort>,
+
+    #[argh(option, short = 'g')]
+    #[argh(description = "only list tips of the group, such as 'eloquent'")]
+    group: Option<String>,
+
+    #[argh(option, short = 's')]
+    #[argh(description = "only list tips of the source, such as 'default'")]
+    source: Option<String>,
+
+    #[argh(switch, short = 't')]
+    #[argh(description = "only print the id, group and title of each tip, one per line")]
+    titles: bool,
+}
+
+impl ListCommand {
+    fn page(&self) -> anyhow::Result<Page> {
+        let offset = match (self.offset, self.page) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!("--offset and --page can not be used together"))
+            }
+            (_, Some(0)) => return Err(anyhow!("--page starts from 1")),
+            (Some(offset), None) => offset,
+            (None, Some(page)) => (page - 1) * self.limit,
+            (None, None) => 0,
+        };
+
+        Ok(Page {
+            limit: self.limit,
+            offset,
+        })
+    }
+}
+
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "groups")]
+#[argh(description = "list the groups of laravel tips with their number of tips")]
+pub struct GroupsCommand {
+    #[argh(option, short = 's')]
+    #[argh(description = "only count tips of the source, such as 'default'")]
+    source: Option<String>,
+}
+
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "stats")]
+#[argh(description = "show the number of tips, the storage and the last sync")]
+pub struct StatsCommand {}
+
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "parse")]
+#[argh(description = "parse a markdown file and print the detected tips and warnings")]
+pub struct ParseCommand {
+    #[argh(positional)]
+    #[argh(
+        description = "the markdown file to parse, can be base64 encoded like the github api responses"
+    )]
+    file: String,
+}
+
+/// The tip with the given id does not exist, exits with a dedicated code
+#[derive(Debug)]
+pub struct NotFound {
+    pub id: String,
+}
+
+impl Display for NotFound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "tip not found: {}, run [search] to find its id or [sync] to refresh tips",
+            self.id
+        )
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+impl SyncCommand {
+    /// The upstream from environment variables, overridden by command line flags
+    fn upstream(&self) -> github::Upstream {
+        let mut upstream = github::Upstream::from_env();
+
+        if let Some(api) = &self.api {
+            upstream.api = api.clone();
+        }
+        if let Some(owner) = &self.owner {
+            upstream.owner = owner.clone();
+        }
+        if let Some(repo) = &self.repo {
+            upstream.repo = repo.clone();
+        }
+        if let Some(reference) = &self.reference {
+            upstream.reference = reference.clone();
+        }
+        if !self.include.is_empty() {
+            upstream.include = self.include.clone();
+        }
+        if !self.exclude.is_empty() {
+            upstream.exclude = self.exclude.clone();
+        }
+
+        upstream
+    }
+
+    /// The sources to sync, and whether they are all the configured sources
+    ///
+    /// `--from` and `--archive` sync a single source named by `--name`, otherwise
+    /// the sources in sources.json are synced, or the github upstream if not configured.
+    fn sources(&self, opts: &Opts) -> anyhow::Result<(Vec<Source>, bool)> {
+        let name = self
+            .name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SOURCE.to_string());
+        let kind = match (&self.from, &self.archive) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!("--from and --archive can not be used together"))
+            }
+            (Some(dir), None) => Some(SourceKind::Dir(PathBuf::from(dir))),
+            (None, Some(src)) => Some(SourceKind::Archive(src.clone())),
+            (None, None) => None,
+        };
+
+        if let Some(kind) = kind {
+            return Ok((vec![Source { name, kind }], false));
+        }
+
+        let path = normalize_path(SOURCES_FILE.to_string(), opts.path.clone())?;
+        let sources = match source::load_sources(&PathBuf::from(&path), &self.upstream())? {
+            Some(sources) => sources,
+            None => {
+                let kind = SourceKind::Github(self.upstream());
+                return Ok((vec![Source { name, kind }], false));
+            }
+        };
+
+        match &self.name {
+            None => Ok((sources, true)),
+            Some(name) => {
+                let source = sources
+                    .into_iter()
+                    .find(|s| &s.name == name)
+                    .ok_or_else(|| anyhow!("unknown source: {}, check {}", name, path))?;
+
+                Ok((vec![source], false))
+            }
+        }
+    }
 }
 
 #[async_trait]
 impl Commander for SyncCommand {
     async fn execute(&self, storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
-        if !opts.quiet {
-            log!("Start sync all laravel tips from LaravelDaily/laravel-tips");
+        let (sources, all) = self.sources(opts)?;
+
+        // the lock is held until the new tips and state are written
+        let _lock = match self.dry_run {
+            true => None,
+            false => Some(storage.lock()?),
+        };
+
+        let mut state = storage.state().await?;
+        let existing = storage.all().await?;
+
+        // keep the tips of the sources not synced this time
+        let mut entities: Vec<Entity> = existing
+            .iter()
+            .filter(|e| !all && !sources.iter().any(|s| s.name == e.source))
+            .cloned()
+            .collect();
+
+        let cache = match self.no_cache {
+            true => None,
+            false => {
+                let dir = normalize_path(HTTP_CACHE_DIR.to_string(), opts.path.clone())?;
+                Some(HttpCache::new(PathBuf::from(dir)))
+            }
+        };
+        let http = github::Http::new(self.concurrency, Duration::from_secs(self.timeout), cache)?;
+
+        let mut report = SyncReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+        let now = utils::unix_now();
+        for source in &sources {
+            let last = match state.sources.get(&source.name) {
+                Some(last) if !self.force => last.clone(),
+                _ => SourceState::default(),
+            };
+            let existing: Vec<&Entity> = existing
+                .iter()
+                .filter(|e| e.source == source.name)
+                .collect();
+
+            let before = entities.len();
+            let mut source_report = SourceReport {
+                name: source.name.clone(),
+                location: source.location(),
+                ..Default::default()
+            };
+
+            match source.fetch(&http, &last, opts.quiet).await {
+                // keep the tips and state of the last sync if the whole source fails
+                Err(err) => {
+                    source_report.failures.push(Failure::new("", &err));
+                    entities.extend(existing.into_iter().cloned());
+                }
+                Ok(None) => {
+                    if !opts.quiet {
+                        log!(" already up to date, skip downloading");
+                    }
+
+                    source_report.up_to_date = true;
+                    entities.extend(existing.into_iter().cloned());
+                    if let Some(last) = state.sources.get_mut(&source.name) {
+                        last.synced_at = now;
+                    }
+                }
+                Ok(Some(mut changes)) => {
+                    if !opts.quiet && !changes.unchanged.is_empty() {
+                        log!(format!(
+                            " {} files unchanged since last sync",
+                            changes.unchanged.len()
+                        ));
+                    }
+
+                    // keep the tips of unchanged files, and replace the others
+                    entities.extend(existing.into_iter().filter(|e| changes.keeps(e)).cloned());
+                    entities.extend(changes.entities.into_iter().map(|mut e| {
+                        e.source = source.name.clone();
+                        e
+                    }));
+                    changes.state.synced_at = now;
+                    state.sources.insert(source.name.clone(), changes.state);
+                    source_report.failures = changes.failures;
+                }
+            }
+
+            source_report.tips = entities.len() - before;
+            report.sources.push(source_report);
         }
 
-        let entities = github::parse_all_laravel_tips(opts.quiet).await?;
+        utils::assign_ids(&mut entities);
+        report.diff = Diff::new(&existing, &entities);
+
+        if !self.dry_run {
+            // all the configured sources are synced, drop the state of removed sources
+            if all {
+                state
+                    .sources
+                    .retain(|name, _| sources.iter().any(|s| &s.name == name));
+            }
+
+            // tips are replaced before the state, if anything fails in between,
+            // the next sync downloads the changed files again
+            storage.store(entities).await?;
+            storage.save_state(state).await?;
+        }
 
-        storage.store(entities).await?;
+        report.print(&opts.format)?;
 
-        if !opts.quiet {
+        report.check(self.allow_partial)?;
+
+        if !opts.quiet && !self.dry_run && opts.format != OutputFormat::Json {
             success!("Sync all laravel tips from successfully, run [random] to get a lucky tip");
         }
 
@@ -84,7 +473,12 @@ impl Commander for SyncCommand {
 #[async_trait]
 impl Commander for RandomCommand {
     async fn execute(&self, storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
-        let result = storage.random().await?;
+        let filter = Filter {
+            source: self.source.clone(),
+            ..Default::default()
+        };
+
+        let result = storage.random(&filter).await?;
         let e = result.ok_or_else(|| {
             anyhow::anyhow!("can not load tips from disk, please run [sync] first")
         })?;
@@ -96,8 +490,92 @@ impl Commander for RandomCommand {
 #[async_trait]
 impl Commander for SearchCommand {
     async fn execute(&self, storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
-        let entities = storage.search(&self.keyword, self.group.as_deref()).await?;
+        let filter = Filter {
+            group: self.group.clone(),
+            source: self.source.clone(),
+        };
+
+        let entities = storage.search(&self.keyword, &filter).await?;
 
         Pretty::new(opts.format.clone()).print_tips(entities)
     }
 }
+
+#[async_trait]
+impl Commander for ShowCommand {
+    async fn execute(&self, storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
+        let e = storage.get(&self.id).await?.ok_or_else(|| NotFound {
+            id: self.id.clone(),
+        })?;
+
+        Pretty::new(opts.format.clone()).print_tip(e)
+    }
+}
+
+#[async_trait]
+impl Commander for ListCommand {
+    async fn execute(&self, storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
+        let filter = Filter {
+            group: self.group.clone(),
+            source: self.source.clone(),
+        };
+        let sort = self.sort.clone().unwrap_or_default();
+
+        let entities = storage.list(&filter, &sort, &self.page()?).await?;
+        let pretty = Pretty::new(opts.format.clone());
+
+        match self.titles {
+            true => pretty.print_titles(entities),
+            false => pretty.print_tips(entities),
+        }
+    }
+}
+
+#[async_trait]
+impl Commander for GroupsCommand {
+    async fn execute(&self, storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
+        let filter = Filter {
+            source: self.source.clone(),
+            ..Default::default()
+        };
+
+        let groups = storage.groups(&filter).await?;
+
+        Pretty::new(opts.format.clone()).print_groups(groups)
+    }
+}
+
+#[async_trait]
+impl Commander for StatsCommand {
+    async fn execute(&self, storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
+        let entities = storage.all().await?;
+        let state = storage.state().await?;
+
+        Stats::new(&entities, &state, storage.info()?).print(&opts.format)
+    }
+}
+
+#[async_trait]
+impl Commander for ParseCommand {
+    async fn execute(&self, _storage: Box<dyn Storage>, opts: &Opts) -> anyhow::Result<()> {
+        let content = std::fs::read_to_string(&self.file)
+            .map_err(|e| anyhow!("can not read {}: {}", self.file, e))?;
+
+        // markdown is never valid base64, it has spaces or punctuation such as `#`
+        let (content, base64) = match utils::base64_decode(content.clone()) {
+            Ok(decoded) => (decoded, true),
+            Err(_) => (content, false),
+        };
+
+        let tips = markdown::parse_tips(&content);
+        let report = ParseReport {
+            file: self.file.clone(),
+            group: utils::group_from_path(&self.file),
+            base64,
+            warnings: markdown::diagnose(&content, &tips),
+            tips,
+        };
+
+        report.print(&opts.format)
+    }
+}
@@ -1,38 +1,212 @@
-use crate::model::{Entity, Tip};
-use crate::{log, utils};
-use reqwest::header::HeaderValue;
+use crate::cache::HttpCache;
+use crate::model::{Entity, SourceState};
+use crate::report::Failure;
+use crate::{log, markdown, utils};
+use glob::Pattern;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::Semaphore;
 
 const ENV_LARAVEL_TIPS_ACCESS_TOKEN: &str = "LARAVEL_TIPS_ACCESS_TOKEN";
 const ENV_HTTP_USER_AGENT: &str = "LARAVEL_TIPS_HTTP_USER_AGENT";
 const ENV_HTTP_ACCEPR: &str = "LARAVEL_TIPS_HTTP_ACCEPR";
+const ENV_API_BASE_URL: &str = "LARAVEL_TIPS_API_BASE_URL";
+const ENV_REPO_OWNER: &str = "LARAVEL_TIPS_REPO_OWNER";
+const ENV_REPO_NAME: &str = "LARAVEL_TIPS_REPO_NAME";
+const ENV_REPO_REF: &str = "LARAVEL_TIPS_REPO_REF";
+const ENV_INCLUDE: &str = "LARAVEL_TIPS_INCLUDE";
+const ENV_EXCLUDE: &str = "LARAVEL_TIPS_EXCLUDE";
 
-const GITHUB_TREES_API: &str =
-    "https://api.github.com/repos/LaravelDaily/laravel-tips/git/trees/master?recursive=1";
+const ENV_HTTP_RETRIES: &str = "LARAVEL_TIPS_HTTP_RETRIES";
+const ENV_RATE_LIMIT_MAX_WAIT: &str = "LARAVEL_TIPS_RATE_LIMIT_MAX_WAIT";
+
+const DEFAULT_HTTP_RETRIES: u64 = 3;
+/// Wait for the rate limit to reset if it resets within this many seconds
+const DEFAULT_RATE_LIMIT_MAX_WAIT: u64 = 60;
+const BACKOFF_BASE_MILLIS: u64 = 500;
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+const DEFAULT_API_BASE_URL: &str = "https://api.github.com";
+const DEFAULT_REPO_OWNER: &str = "LaravelDaily";
+const DEFAULT_REPO_NAME: &str = "laravel-tips";
+const DEFAULT_REPO_REF: &str = "master";
+
+/// The upstream repository to sync tips from
+///
+/// Every field can be set by environment variables, the include and exclude
+/// variables accept a comma separated list of globs, such as `eloquent*.md,arr.md`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upstream {
+    /// The api base url, change it for GitHub Enterprise or a local mirror server
+    pub api: String,
+    pub owner: String,
+    pub repo: String,
+    /// The branch, tag or commit sha to sync
+    pub reference: String,
+    /// Only sync the files matching one of these globs, sync all if empty
+    pub include: Vec<String>,
+    /// Skip the files matching one of these globs
+    pub exclude: Vec<String>,
+}
+
+impl Default for Upstream {
+    fn default() -> Self {
+        Self {
+            api: DEFAULT_API_BASE_URL.to_string(),
+            owner: DEFAULT_REPO_OWNER.to_string(),
+            repo: DEFAULT_REPO_NAME.to_string(),
+            reference: DEFAULT_REPO_REF.to_string(),
+            include: vec![],
+            exclude: vec![],
+        }
+    }
+}
+
+impl Upstream {
+    /// Load the upstream from environment variables, fallback to LaravelDaily/laravel-tips
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let var = |key: &str, default: String| std::env::var(key).unwrap_or(default);
+        let globs = |key: &str| {
+            std::env::var(key)
+                .map(|v| split_globs(&v))
+                .unwrap_or_default()
+        };
+
+        Self {
+            api: var(ENV_API_BASE_URL, default.api),
+            owner: var(ENV_REPO_OWNER, default.owner),
+            repo: var(ENV_REPO_NAME, default.repo),
+            reference: var(ENV_REPO_REF, default.reference),
+            include: globs(ENV_INCLUDE),
+            exclude: globs(ENV_EXCLUDE),
+        }
+    }
+
+    /// The full name of the repository, such as `LaravelDaily/laravel-tips`
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.owner, self.repo)
+    }
+
+    /// Identify the upstream, its filters and the parser version, the sync state
+    /// of another upstream or parsed by another parser can not be reused.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "{}/{}@{} include={} exclude={} parser={}",
+            self.api.trim_end_matches('/'),
+            self.name(),
+            self.reference,
+            self.include.join(","),
+            self.exclude.join(","),
+            markdown::PARSER_VERSION
+        )
+    }
+
+    /// The permanent url of the file at the `commit` on the website, such as
+    /// `https://github.com/LaravelDaily/laravel-tips/blob/0123456/api.md`, it
+    /// still points to the synced content after the branch moves on
+    pub fn blob_url(&self, commit: &str, path: &str) -> String {
+        // the api of github enterprise is served under `/api/v3` of the website
+        let api = self.api.trim_end_matches('/');
+        let web = match api {
+            DEFAULT_API_BASE_URL => "https://github.com",
+            _ => api.trim_end_matches("/api/v3"),
+        };
+
+        format!(
+            "{}/{}/blob/{}/{}",
+            web,
+            self.name(),
+            commit,
+            path.replace(' ', "%20")
+        )
+    }
+
+    fn commit_url(&self) -> String {
+        format!(
+            "{}/repos/{}/commits/{}",
+            self.api.trim_end_matches('/'),
+            self.name(),
+            self.reference
+        )
+    }
+
+    /// The url of the tree with the `sha`, fetch it by sha rather than by the
+    /// reference so that the tree always belongs to the fetched commit
+    fn trees_url(&self, sha: &str) -> String {
+        format!(
+            "{}/repos/{}/git/trees/{}?recursive=1",
+            self.api.trim_end_matches('/'),
+            self.name(),
+            sha
+        )
+    }
+
+    /// Check if the file should be synced according to the include and exclude globs
+    pub fn is_included(&self, path: &str) -> bool {
+        let matches = |globs: &[String]| {
+            globs
+                .iter()
+                .any(|g| Pattern::new(g).map(|p| p.matches(path)).unwrap_or(false))
+        };
+
+        utils::is_tips_file(path)
+            && (self.include.is_empty() || matches(&self.include))
+            && !matches(&self.exclude)
+    }
+}
+
+/// Split a comma separated list of globs
+pub fn split_globs(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty())
+        .collect()
+}
 
 #[derive(Debug, Deserialize)]
 struct Trees {
+    sha: String,
     tree: Vec<Tree>,
 }
 
+/// The commit struct for github api response, only the fields we need
+///
+/// see [commits](https://docs.github.com/en/rest/commits/commits?apiVersion=2022-11-28#get-a-commit)
+#[derive(Debug, Deserialize)]
+struct Commit {
+    sha: String,
+    commit: CommitDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitDetail {
+    tree: TreeRef,
+}
+
+#[derive(Debug, Deserialize)]
+struct TreeRef {
+    sha: String,
+}
+
 /// The tree struct for github api response
 ///
 /// see [git/trees](https://docs.github.com/en/rest/git/trees?apiVersion=2022-11-28#get-a-tree)
 #[derive(Debug, Deserialize)]
 pub struct Tree {
     path: String,
+    sha: String,
     url: String,
 }
 
 impl Tree {
-    /// Check if the file is readme.md
-    pub fn is_readme(&self) -> bool {
-        self.path == "README.md"
-    }
-
     /// Get the file content, note that the content is base64 encoded
-    pub async fn get_content(&self, quiet: bool) -> anyhow::Result<String> {
+    pub async fn get_content(&self, http: &Http, quiet: bool) -> anyhow::Result<String> {
         if !quiet {
             log!(format!(" parsing file: {}", &self.path));
         }
@@ -42,57 +216,304 @@ impl Tree {
             content: String,
         }
 
-        let res = http_get::<Content>(&self.url).await?;
+        let res = http.get::<Content>(&self.url).await?;
 
         Ok(res.content)
     }
 }
 
-pub async fn parse_all_laravel_tips(quiet: bool) -> anyhow::Result<Vec<Entity>> {
-    // 1. get all tips file from the laravel-tips repository
-    let trees: Vec<Tree> = get_laravel_tips_trees().await?;
-    let mut entities: Vec<Entity> = Vec::new();
+/// The tips changed since the last sync
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// The upstream state after this sync
+    pub state: SourceState,
+    /// Tips parsed from the files changed since the last sync
+    pub entities: Vec<Entity>,
+    /// Paths of the files not changed since the last sync, their tips should be kept
+    pub unchanged: Vec<String>,
+    /// Files failed to download or parse
+    pub failures: Vec<Failure>,
+}
+
+impl Changes {
+    fn keep(&mut self, tree: &Tree, sha: String) {
+        self.unchanged.push(tree.path.clone());
+        self.state.files.insert(tree.path.clone(), sha);
+    }
+
+    /// Whether the tip synced last time comes from an unchanged file and should be kept
+    pub fn keeps(&self, entity: &Entity) -> bool {
+        self.unchanged.contains(&entity.path)
+    }
+}
+
+/// Parse the tips of the files changed since the `last` sync
+///
+/// Returns `None` if the upstream tree is not changed at all. Files that fail to
+/// download or parse keep their old tips and sha, so they will be retried next time.
+pub async fn parse_all_laravel_tips(
+    http: &Http,
+    upstream: &Upstream,
+    last: &SourceState,
+    quiet: bool,
+) -> anyhow::Result<Option<Changes>> {
+    let fingerprint = upstream.fingerprint();
+    let last = if last.upstream == fingerprint {
+        last.clone()
+    } else {
+        SourceState::default()
+    };
+
+    // 1. get all tips file from the tree of the latest commit
+    let commit = http.get_cached::<Commit>(&upstream.commit_url()).await?;
+    if !last.sha.is_empty() && last.sha == commit.commit.tree.sha {
+        return Ok(None);
+    }
+    let trees = get_laravel_tips_trees(http, upstream, &commit.commit.tree.sha).await?;
 
-    // 2. generate the tasks for each file
-    let tasks: Vec<_> = trees
+    let mut changes = Changes {
+        state: SourceState {
+            sha: trees.sha,
+            upstream: fingerprint,
+            commit: commit.sha,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let (unchanged, changed): (Vec<_>, Vec<_>) = trees
+        .tree
         .iter()
-        .filter(|tree| !tree.is_readme())
-        .map(|t| t.get_content(quiet))
+        .filter(|tree| upstream.is_included(&tree.path))
+        .partition(|tree| last.files.get(&tree.path) == Some(&tree.sha));
+
+    for tree in unchanged {
+        changes.keep(tree, tree.sha.clone());
+    }
+
+    // 2. generate the tasks for each changed file, the number of in-flight
+    // requests is limited by the http client
+    let tasks: Vec<_> = changed
+        .into_iter()
+        .map(|t| async move { (t, t.get_content(http, quiet).await) })
         .collect();
 
     // 3. wait for all tasks to complete
     let result = futures::future::join_all(tasks).await;
-    for content in result.into_iter().flatten() {
-        if let Ok(tips) = utils::parse_tips(content) {
-            entities.extend(convert_tips_to_entities(tips));
+    for (tree, content) in result {
+        match content.and_then(utils::parse_tips) {
+            Ok(tips) => {
+                changes.entities.extend(utils::convert_tips_to_entities(
+                    tips,
+                    &tree.path,
+                    &upstream.blob_url(&changes.state.commit, &tree.path),
+                ));
+                changes
+                    .state
+                    .files
+                    .insert(tree.path.clone(), tree.sha.clone());
+            }
+            Err(err) => {
+                changes.failures.push(Failure::new(&tree.path, &err));
+                changes.state.sha.clear();
+                if let Some(sha) = last.files.get(&tree.path) {
+                    changes.keep(tree, sha.clone());
+                }
+            }
         }
     }
 
-    Ok(entities)
+    Ok(Some(changes))
 }
 
 /// Get all tips file from the github repository
 ///
-/// We will get the all files from the LaravelDaily/laravel-tips repository
-async fn get_laravel_tips_trees() -> anyhow::Result<Vec<Tree>> {
-    let res = http_get::<Trees>(GITHUB_TREES_API).await?;
+/// We will get the all files of the tree with the `sha` from the upstream repository,
+/// LaravelDaily/laravel-tips by default
+async fn get_laravel_tips_trees(
+    http: &Http,
+    upstream: &Upstream,
+    sha: &str,
+) -> anyhow::Result<Trees> {
+    http.get::<Trees>(&upstream.trees_url(sha)).await
+}
+
+/// The error of a request answered with a non-success status
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: u16,
+    pub body: String,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http status {}, error: {}", self.status, self.body)
+    }
+}
 
-    Ok(res.tree)
+impl std::error::Error for HttpError {}
+
+/// The http client shared by all requests of a sync
+///
+/// Connections are pooled by the inner client, and the semaphore limits the
+/// number of in-flight requests so large repositories don't trip GitHub's
+/// secondary rate limits.
+pub struct Http {
+    client: reqwest::Client,
+    permits: Semaphore,
+    retries: u32,
+    max_wait: Duration,
+    cache: Option<HttpCache>,
 }
 
-fn convert_tips_to_entities(tips: Vec<Tip>) -> Vec<Entity> {
-    tips.into_iter()
-        //@todo adding more fields when converting from utils::Tip to Entity, such as code(php/blade/html), author, link, etc.
-        .map(|t| Entity {
-            id: "".to_string(),
-            title: t.title,
-            content: t.content,
+impl Http {
+    /// Create a client allowing `concurrency` in-flight requests, each request
+    /// including reading the body must finish within `timeout`.
+    ///
+    /// Responses of mutable urls are cached with their `ETag` if `cache` is given,
+    /// and sent with `If-None-Match` next time, GitHub does not count
+    /// `304 Not Modified` against the rate limit.
+    pub fn new(
+        concurrency: usize,
+        timeout: Duration,
+        cache: Option<HttpCache>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: new_client(timeout)?,
+            permits: Semaphore::new(concurrency.max(1)),
+            cache,
+            retries: env_number(ENV_HTTP_RETRIES, DEFAULT_HTTP_RETRIES) as u32,
+            max_wait: Duration::from_secs(env_number(
+                ENV_RATE_LIMIT_MAX_WAIT,
+                DEFAULT_RATE_LIMIT_MAX_WAIT,
+            )),
         })
-        .collect()
+    }
+
+    /// Basic http get method, for immutable urls such as a tree or blob by sha
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> anyhow::Result<T> {
+        let body = self.fetch(url, false).await?;
+        let res = serde_json::from_slice::<T>(&body)?;
+
+        Ok(res)
+    }
+
+    /// Get a mutable url such as the latest commit of a branch, the response is
+    /// cached and revalidated with its `ETag`
+    async fn get_cached<T: DeserializeOwned>(&self, url: &str) -> anyhow::Result<T> {
+        let body = self.fetch(url, true).await?;
+        let res = serde_json::from_slice::<T>(&body)?;
+
+        Ok(res)
+    }
+
+    /// Download the raw response body, such as a tarball or zipball of the repository
+    ///
+    /// Archives are not cached, they are too large to keep a copy of every version.
+    pub async fn download(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        self.fetch(url, false).await
+    }
+
+    /// Get the response body, only mutable urls worth a `revalidate` are cached
+    async fn fetch(&self, url: &str, revalidate: bool) -> anyhow::Result<Vec<u8>> {
+        let _permit = self.permits.acquire().await?;
+
+        let cache = self.cache.as_ref().filter(|_| revalidate);
+        let cached = cache.and_then(|c| c.load(url));
+        let etag = cached.as_ref().map(|c| c.etag.as_str());
+
+        let response = self.send_get(url, etag).await?;
+        if response.status == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                return Ok(cached.body);
+            }
+        }
+
+        // a broken cache only costs a full request next time
+        if let (Some(cache), Some(etag)) = (cache, &response.etag) {
+            let _ = cache.save(url, etag, &response.body);
+        }
+
+        Ok(response.body)
+    }
+
+    /// Send a get request and read its body, transient failures are retried with
+    /// exponential backoff
+    ///
+    /// Server errors, timeouts and connection errors are retried, including those
+    /// while reading the body, rate limited responses wait until the limit resets
+    /// if it is soon enough, otherwise fail with a message telling when to retry.
+    async fn send_get(&self, url: &str, etag: Option<&str>) -> anyhow::Result<Response> {
+        let mut attempt: u32 = 0;
+        loop {
+            let mut request = self.client.get(url);
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+
+            let wait = match request.send().await {
+                Ok(response)
+                    if response.status().is_success()
+                        || response.status() == StatusCode::NOT_MODIFIED =>
+                {
+                    let status = response.status();
+                    let etag = response
+                        .headers()
+                        .get(ETAG)
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_string());
+
+                    match response.bytes().await {
+                        Ok(body) => {
+                            return Ok(Response {
+                                status,
+                                etag,
+                                body: body.to_vec(),
+                            })
+                        }
+                        Err(err) if is_transient(&err) && attempt < self.retries => {
+                            backoff(attempt)
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let rate_limit = rate_limit_wait(status, response.headers(), utils::unix_now());
+                    let error = HttpError {
+                        status: status.as_u16(),
+                        body: response.text().await.unwrap_or_default(),
+                    };
+
+                    match rate_limit {
+                        Some(wait) if wait <= self.max_wait && attempt < self.retries => wait,
+                        Some(wait) => {
+                            return Err(anyhow::Error::new(error).context(rate_limit_message(wait)))
+                        }
+                        None if status.is_server_error() && attempt < self.retries => {
+                            backoff(attempt)
+                        }
+                        None => return Err(error.into()),
+                    }
+                }
+                Err(err) if is_transient(&err) && attempt < self.retries => backoff(attempt),
+                Err(err) => return Err(err.into()),
+            };
+
+            attempt += 1;
+            tokio::time::sleep(wait).await;
+        }
+    }
 }
 
-/// Basic http get method,
-async fn http_get<T: DeserializeOwned>(url: &str) -> anyhow::Result<T> {
+/// A successful or not modified response with its body
+struct Response {
+    status: StatusCode,
+    etag: Option<String>,
+    body: Vec<u8>,
+}
+
+fn new_client(timeout: Duration) -> anyhow::Result<reqwest::Client> {
     let mut headers = reqwest::header::HeaderMap::new();
     let agent = std::env::var(ENV_HTTP_USER_AGENT).unwrap_or_else(|_| "laravel-tips".to_string());
     let accept = std::env::var(ENV_HTTP_ACCEPR)
@@ -116,18 +537,293 @@ async fn http_get<T: DeserializeOwned>(url: &str) -> anyhow::Result<T> {
 
     let client = reqwest::Client::builder()
         .default_headers(headers)
+        .connect_timeout(timeout.min(Duration::from_secs(CONNECT_TIMEOUT_SECS)))
+        .timeout(timeout)
         .build()?;
 
-    let response = client.get(url).send().await?;
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "{}, error: {}",
-            response.status(),
-            response.text().await?
+    Ok(client)
+}
+
+fn env_number(key: &str, default: u64) -> u64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Timeouts, connection errors such as resets are worth retrying
+fn is_transient(err: &reqwest::Error) -> bool {
+    // a body error is the connection closed or reset while reading the body
+    err.is_timeout() || err.is_connect() || err.is_request() || err.is_body()
+}
+
+/// The exponential backoff before the next attempt, with up to 100% jitter
+fn backoff(attempt: u32) -> Duration {
+    let base = BACKOFF_BASE_MILLIS.saturating_mul(1 << attempt.min(10));
+    let jitter = rand::thread_rng().gen_range(0..=base);
+
+    Duration::from_millis(base + jitter)
+}
+
+/// How long to wait if the response is rate limited, `None` if it is not
+///
+/// GitHub answers `403` or `429` with `Retry-After` for secondary rate limits,
+/// and `X-RateLimit-Remaining: 0` with `X-RateLimit-Reset` for the primary one.
+fn rate_limit_wait(status: StatusCode, headers: &HeaderMap, now: u64) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+
+    let header = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+
+    if let Some(seconds) = header("retry-after") {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    if header("x-ratelimit-remaining") == Some(0) {
+        let reset = header("x-ratelimit-reset").unwrap_or(now);
+        return Some(Duration::from_secs(reset.saturating_sub(now)));
+    }
+
+    // a 429 without any header is still a rate limit
+    (status == StatusCode::TOO_MANY_REQUESTS).then(|| Duration::from_secs(60))
+}
+
+fn rate_limit_message(wait: Duration) -> String {
+    let minutes = wait.as_secs().div_ceil(60);
+    let mut message = format!(
+        "GitHub API rate limit exceeded, please retry in {} minute(s)",
+        minutes
+    );
+
+    if std::env::var(ENV_LARAVEL_TIPS_ACCESS_TOKEN).is_err() {
+        message.push_str(&format!(
+            ", or set {} to a GitHub token to raise the limit",
+            ENV_LARAVEL_TIPS_ACCESS_TOKEN
         ));
     }
 
-    let res = response.json::<T>().await?;
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_upstream_trees_url() {
+        let upstream = Upstream {
+            api: "https://github.example.com/api/v3/".to_string(),
+            owner: "acme".to_string(),
+            repo: "tips".to_string(),
+            reference: "main".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            upstream.trees_url("9fb037999f264ba9a7fc6274d15fa3ae2ab98312"),
+            "https://github.example.com/api/v3/repos/acme/tips/git/trees/9fb037999f264ba9a7fc6274d15fa3ae2ab98312?recursive=1"
+        );
+        assert_eq!(
+            upstream.blob_url("0123456", "api.md"),
+            "https://github.example.com/acme/tips/blob/0123456/api.md"
+        );
+        assert_eq!(
+            Upstream::default().blob_url("0123456", "db models and eloquent.md"),
+            "https://github.com/LaravelDaily/laravel-tips/blob/0123456/db%20models%20and%20eloquent.md"
+        );
+        assert_eq!(
+            upstream.commit_url(),
+            "https://github.example.com/api/v3/repos/acme/tips/commits/main"
+        );
+        assert_eq!(
+            Upstream::default().trees_url("9fb0379"),
+            "https://api.github.com/repos/LaravelDaily/laravel-tips/git/trees/9fb0379?recursive=1"
+        );
+    }
+
+    #[test]
+    fn test_commit_tree_sha() {
+        let commit: Commit = serde_json::from_str(
+            r#"{
+                "sha": "0123456789abcdef0123456789abcdef01234567",
+                "commit": {"message": "Add tips", "tree": {"sha": "9fb0379", "url": ""}}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(commit.sha, "0123456789abcdef0123456789abcdef01234567");
+        assert_eq!(commit.commit.tree.sha, "9fb0379");
+    }
 
-    Ok(res)
+    #[test]
+    fn test_upstream_is_included() {
+        let upstream = Upstream {
+            include: split_globs("eloquent*.md, arr.md"),
+            exclude: split_globs("eloquent-old.md"),
+            ..Default::default()
+        };
+
+        assert!(upstream.is_included("eloquent.md"));
+        assert!(upstream.is_included("eloquent-relations.md"));
+        assert!(upstream.is_included("arr.md"));
+        assert!(!upstream.is_included("eloquent-old.md"));
+        assert!(!upstream.is_included("artisan.md"));
+
+        let all = Upstream::default();
+        assert!(all.is_included("artisan.md"));
+        assert!(!all.is_included("README.md"));
+        assert!(!all.is_included("images/logo.png"));
+    }
+
+    #[test]
+    fn test_changes_keeps_tips_by_path() {
+        let tree = |path: &str| Tree {
+            path: path.to_string(),
+            sha: "sha".to_string(),
+            url: String::new(),
+        };
+        let entity = |path: &str| Entity {
+            group: "eloquent".to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+
+        let mut changes = Changes::default();
+        changes.keep(&tree("eloquent.md"), "sha".to_string());
+
+        assert!(changes.keeps(&entity("eloquent.md")));
+        assert!(!changes.keeps(&entity("archive/eloquent.md")));
+        assert!(!changes.keeps(&entity("")));
+    }
+
+    #[test]
+    fn test_upstream_fingerprint() {
+        let upstream = Upstream {
+            include: split_globs("eloquent*.md"),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            upstream.fingerprint(),
+            format!(
+                "https://api.github.com/LaravelDaily/laravel-tips@master include=eloquent*.md exclude= parser={}",
+                markdown::PARSER_VERSION
+            )
+        );
+        assert_ne!(upstream.fingerprint(), Upstream::default().fingerprint());
+    }
+
+    #[test]
+    fn test_rate_limit_wait() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &headers, 100), None);
+        assert_eq!(
+            rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &headers, 100),
+            Some(Duration::from_secs(60))
+        );
+
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("0"));
+        headers.insert("x-ratelimit-reset", HeaderValue::from_static("130"));
+        assert_eq!(
+            rate_limit_wait(StatusCode::FORBIDDEN, &headers, 100),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(rate_limit_wait(StatusCode::NOT_FOUND, &headers, 100), None);
+
+        headers.insert("retry-after", HeaderValue::from_static("5"));
+        assert_eq!(
+            rate_limit_wait(StatusCode::FORBIDDEN, &headers, 100),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        for attempt in 0..4 {
+            let base = BACKOFF_BASE_MILLIS * (1 << attempt);
+            let wait = backoff(attempt).as_millis() as u64;
+
+            assert!(wait >= base && wait <= base * 2);
+        }
+    }
+
+    /// Answer each connection with the next response, returns the url of the
+    /// server and a handle to the requests it received
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/commits/master", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    stream.write_all(response.as_bytes()).unwrap();
+
+                    String::from_utf8_lossy(&request).to_lowercase()
+                })
+                .collect()
+        });
+
+        (url, handle)
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 13\r\nConnection: close\r\n\r\n{\"sha\":\"abc\"}";
+    const NOT_MODIFIED: &str =
+        "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n";
+
+    #[derive(Deserialize)]
+    struct Sha {
+        sha: String,
+    }
+
+    #[tokio::test]
+    async fn test_http_revalidates_cached_response() {
+        let dir = TestDir::new("http-revalidate");
+        let (url, server) = serve(vec![OK, NOT_MODIFIED]);
+        let cache = HttpCache::new(dir.to_path_buf());
+        let http = Http::new(1, Duration::from_secs(5), Some(cache)).unwrap();
+
+        // the second response has no body, it must come from the cache
+        assert_eq!(http.get_cached::<Sha>(&url).await.unwrap().sha, "abc");
+        assert_eq!(http.get_cached::<Sha>(&url).await.unwrap().sha, "abc");
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+    }
+
+    #[tokio::test]
+    async fn test_http_retries_broken_body() {
+        // the connection is closed before the whole body is sent
+        const BROKEN: &str =
+            "HTTP/1.1 200 OK\r\nContent-Length: 13\r\nConnection: close\r\n\r\n{\"sha\"";
+
+        let (url, server) = serve(vec![BROKEN, OK]);
+        let http = Http::new(1, Duration::from_secs(5), None).unwrap();
+
+        assert_eq!(http.get::<Sha>(&url).await.unwrap().sha, "abc");
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_http_does_not_cache_immutable_response() {
+        let dir = TestDir::new("http-immutable");
+        let (url, server) = serve(vec![OK]);
+        let cache = HttpCache::new(dir.to_path_buf());
+        let http = Http::new(1, Duration::from_secs(5), Some(cache)).unwrap();
+
+        assert_eq!(http.get::<Sha>(&url).await.unwrap().sha, "abc");
+        server.join().unwrap();
+        assert!(HttpCache::new(dir.to_path_buf()).load(&url).is_none());
+    }
 }
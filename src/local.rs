@@ -0,0 +1,106 @@
+use crate::model::Entity;
+use crate::report::Failure;
+use crate::{log, markdown, utils};
+use anyhow::anyhow;
+use std::path::{Path, PathBuf};
+
+/// Parse all tips from a local directory of markdown files
+///
+/// The directory is usually a clone of LaravelDaily/laravel-tips, every `*.md`
+/// file except README.md is parsed, hidden directories such as `.git` are skipped.
+/// Files that can not be read are returned as failures.
+pub fn parse_all_local_tips(
+    dir: &Path,
+    quiet: bool,
+) -> anyhow::Result<(Vec<Entity>, Vec<Failure>)> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.to_string_lossy()));
+    }
+
+    let mut files = Vec::new();
+    collect_markdown_files(dir, &mut files)?;
+    files.sort();
+
+    let mut entities = Vec::new();
+    let mut failures = Vec::new();
+    for file in files {
+        let path = file.strip_prefix(dir).unwrap_or(&file).to_string_lossy();
+        if !quiet {
+            log!(format!(" parsing file: {}", path));
+        }
+
+        let content = match std::fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(err) => {
+                failures.push(Failure::new(&path, &err.into()));
+                continue;
+            }
+        };
+        let tips = markdown::parse_tips(&content);
+
+        let url = std::fs::canonicalize(&file)
+            .map(|f| format!("file://{}", f.to_string_lossy().replace(' ', "%20")))
+            .unwrap_or_default();
+
+        entities.extend(utils::convert_tips_to_entities(tips, &path, &url));
+    }
+
+    Ok((entities, failures))
+}
+
+fn collect_markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if path.is_dir() {
+            collect_markdown_files(&path, files)?;
+        } else if utils::is_tips_file(&name) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_parse_all_local_tips() {
+        let dir = TestDir::new("local");
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+
+        std::fs::write(dir.join("README.md"), "### Table of contents\n").unwrap();
+        std::fs::write(dir.join(".git/HEAD.md"), "### Hidden\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "### Not markdown\n").unwrap();
+        std::fs::write(dir.join("broken.md"), [0xff, 0xfe, 0xfd]).unwrap();
+        std::fs::write(
+            dir.join("Eloquent.md"),
+            "# Eloquent\n\n### First\nfirst tip\n\n### Second\nsecond tip\n",
+        )
+        .unwrap();
+
+        let (entities, failures) = parse_all_local_tips(&dir, true).unwrap();
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].path, "broken.md");
+
+        assert_eq!(entities.len(), 2);
+        assert_eq!(entities[0].title, "First");
+        assert_eq!(entities[1].title, "Second");
+        assert_eq!(entities[1].group, "eloquent");
+        assert_eq!(entities[1].path, "Eloquent.md");
+
+        assert!(parse_all_local_tips(&dir.join("not-exists"), true).is_err());
+    }
+}
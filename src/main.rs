@@ -1,16 +1,25 @@
+mod archive;
+mod cache;
 mod github;
+mod local;
+mod markdown;
 mod utils;
 #[macro_use]
 mod ui;
 mod command;
 mod model;
 mod pretty;
+mod report;
+mod source;
 mod storage;
 
 use argh::FromArgs;
 
 const VERSION: &str = "0.0.3";
 
+/// Exit code when the requested tip does not exist
+const EXIT_NOT_FOUND: i32 = 2;
+
 #[derive(FromArgs, Debug)]
 #[argh(description = "A command line tool for laravel tips")]
 pub struct Args {
@@ -46,6 +55,11 @@ enum SubCommands {
     Random(command::RandomCommand),
     Sync(command::SyncCommand),
     Search(command::SearchCommand),
+    Show(command::ShowCommand),
+    List(command::ListCommand),
+    Groups(command::GroupsCommand),
+    Stats(command::StatsCommand),
+    Parse(command::ParseCommand),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -101,7 +115,10 @@ async fn main() {
     if let Err(err) = command::Manager::new(args).execute().await {
         error!(format!("{}", err));
 
-        std::process::exit(1);
+        match err.downcast_ref::<command::NotFound>() {
+            Some(_) => std::process::exit(EXIT_NOT_FOUND),
+            None => std::process::exit(1),
+        }
     }
 }
 
@@ -120,4 +137,9 @@ Commands:
   random            random laravel tips
   sync              sync laravel tips from laravel docs
   search            search laravel tips by keyword
+  show              show a laravel tip by id
+  list              list laravel tips page by page
+  groups            list the groups of laravel tips with their number of tips
+  stats             show the number of tips, the storage and the last sync
+  parse             parse a markdown file and print the detected tips and warnings
 "#;
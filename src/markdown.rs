@@ -0,0 +1,608 @@
+use crate::model::{Snippet, Tip};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Parser, Tag};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The line crediting the author of a tip, such as `Tip given by [@someone](https://twitter.com/someone)`
+const ATTRIBUTION_PREFIX: &str = "tip given by";
+
+/// The arrows starting the navigation lines of upstream files, such as
+/// `⬆️ [Go to main menu](README.md#laravel-tips) ⬅️ [Previous (Log and debug)](log-and-debug.md)`
+const NAVIGATION_ARROWS: [char; 3] = ['⬆', '⬅', '➡'];
+
+/// The version of the parser, bump it whenever the tips synced from the same
+/// markdown change, so that incremental syncs parse every file again
+pub const PARSER_VERSION: u32 = 3;
+
+/// Parse tips from markdown, each level-3 heading starts a new tip
+///
+/// Only real headings count, `###` inside code blocks, block quotes or lists and
+/// `####` subheadings belong to the body. A tip ends at the next heading of
+/// level 3 or above, and its body is the markdown in between, kept verbatim
+/// except the attribution line, which is parsed into the author of the tip,
+/// and navigation artefacts: navigation lines, tables of contents and the
+/// rules separating tips. The code blocks of the body are collected as snippets.
+pub fn parse_tips(markdown: &str) -> Vec<Tip> {
+    let mut tips = Vec::new();
+    let mut current: Option<Current> = None;
+    let mut snippet: Option<Snippet> = None;
+    let mut heading: Option<Heading> = None;
+    let mut anchors = Anchors::default();
+    let mut depth = 0;
+
+    for (event, range) in Parser::new(markdown).into_offset_iter() {
+        match event {
+            Event::Start(Tag::BlockQuote | Tag::List(_) | Tag::FootnoteDefinition(_)) => {
+                if depth == 0 && is_table_of_contents(&markdown[range.clone()]) {
+                    if let Some(current) = current.as_mut() {
+                        current.remove(markdown, range);
+                    }
+                }
+
+                depth += 1
+            }
+            Event::End(Tag::BlockQuote | Tag::List(_) | Tag::FootnoteDefinition(_)) => depth -= 1,
+            Event::Start(Tag::Heading(level, ..)) => {
+                heading = Some(Heading {
+                    level,
+                    range,
+                    title: String::new(),
+                    text: String::new(),
+                });
+            }
+            Event::End(Tag::Heading(..)) => {
+                let Some(Heading {
+                    level,
+                    range,
+                    title,
+                    text,
+                }) = heading.take()
+                else {
+                    continue;
+                };
+                let anchor = anchors.next(&text);
+
+                if depth > 0 || level > HeadingLevel::H3 {
+                    continue;
+                }
+
+                if let Some(current) = current.take() {
+                    tips.push(current.into_tip(&markdown[..range.start]));
+                }
+
+                if level == HeadingLevel::H3 {
+                    current = Some(Current {
+                        title: title.trim().to_string(),
+                        anchor,
+                        position: tips.len() + 1,
+                        heading: range.start,
+                        start: body_start(markdown, range.end),
+                        ..Default::default()
+                    });
+                }
+            }
+            Event::Start(Tag::Paragraph) if depth == 0 => {
+                let Some(current) = current.as_mut() else {
+                    continue;
+                };
+
+                let paragraph = &markdown[range.clone()];
+                if let Some((author, link)) = attribution(paragraph) {
+                    current.author = author;
+                    current.author_link = link;
+                    current.remove(markdown, range);
+                } else if is_navigation(paragraph) {
+                    current.remove(markdown, range);
+                }
+            }
+            Event::Rule if depth == 0 => {
+                if let Some(current) = current.as_mut() {
+                    current.remove(markdown, range);
+                }
+            }
+            Event::Start(Tag::CodeBlock(kind)) if current.is_some() => {
+                snippet = Some(Snippet {
+                    language: language(&kind),
+                    code: String::new(),
+                });
+            }
+            Event::Text(text) => {
+                if let Some(heading) = heading.as_mut() {
+                    heading.title.push_str(&text);
+                    heading.text.push_str(&text);
+                } else if let Some(snippet) = snippet.as_mut() {
+                    snippet.code.push_str(&text);
+                }
+            }
+            Event::Code(code) => {
+                if let Some(heading) = heading.as_mut() {
+                    heading.title.push_str(&format!("`{}`", code));
+                    heading.text.push_str(&code);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let (Some(snippet), Some(current)) = (snippet.take(), current.as_mut()) {
+                    current.snippets.push(snippet);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(current) = current {
+        tips.push(current.into_tip(markdown));
+    }
+
+    tips
+}
+
+/// The heading being parsed, its title and anchor are made of its text, so
+/// links and emphasis don't end up in them
+struct Heading {
+    level: HeadingLevel,
+    range: Range<usize>,
+    /// The text of the heading, code spans keep their backticks
+    title: String,
+    /// The text of the heading without any markup
+    text: String,
+}
+
+/// The tip being parsed, its body starts at `start`
+#[derive(Default)]
+struct Current {
+    title: String,
+    anchor: String,
+    position: usize,
+    /// The offset of the heading
+    heading: usize,
+    start: usize,
+    snippets: Vec<Snippet>,
+    author: String,
+    author_link: String,
+    /// The ranges removed from the body, in order
+    removed: Vec<Range<usize>>,
+}
+
+impl Current {
+    /// Remove the block at `range` from the body, with its line break and one blank line
+    fn remove(&mut self, markdown: &str, range: Range<usize>) {
+        self.removed
+            .push(range.start..line_end(markdown, range.end));
+    }
+
+    /// Finish the tip, its body ends where `markdown` ends
+    fn into_tip(self, markdown: &str) -> Tip {
+        let mut content = String::new();
+        let mut start = self.start;
+        for range in self.removed {
+            content.push_str(&markdown[start..range.start]);
+            start = range.end.min(markdown.len());
+        }
+        content.push_str(&markdown[start..]);
+
+        // the tip ends at its last non blank line
+        let end = markdown.trim_end().len().max(self.heading);
+
+        Tip {
+            title: self.title,
+            content,
+            snippets: self.snippets,
+            author: self.author,
+            author_link: self.author_link,
+            anchor: self.anchor,
+            position: self.position,
+            lines: (line_of(markdown, self.heading), line_of(markdown, end)),
+        }
+    }
+}
+
+/// A problem of a markdown file that likely makes its tips misparsed
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Check the tips parsed from `markdown` for empty bodies, duplicate titles and
+/// unterminated code fences, ordered by line
+pub fn diagnose(markdown: &str, tips: &[Tip]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    let mut titles: HashMap<&str, usize> = HashMap::new();
+    for tip in tips {
+        if tip.content.trim().is_empty() {
+            warnings.push(Warning {
+                line: tip.lines.0,
+                message: format!("tip \"{}\" has an empty body", tip.title),
+            });
+        }
+
+        match titles.get(tip.title.as_str()) {
+            Some(first) => warnings.push(Warning {
+                line: tip.lines.0,
+                message: format!(
+                    "duplicate title \"{}\", first used on line {}",
+                    tip.title, first
+                ),
+            }),
+            None => {
+                titles.insert(&tip.title, tip.lines.0);
+            }
+        }
+    }
+
+    for (event, range) in Parser::new(markdown).into_offset_iter() {
+        if let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_))) = event {
+            if !is_terminated(&markdown[range.clone()]) {
+                warnings.push(Warning {
+                    line: line_of(markdown, range.start),
+                    message: "unterminated code fence, the rest of the block is parsed as code"
+                        .to_string(),
+                });
+            }
+        }
+    }
+
+    warnings.sort_by_key(|w| w.line);
+
+    warnings
+}
+
+/// Check if the fenced code block ends with a closing fence, which is at least
+/// as long as the opening fence and made of the same character
+fn is_terminated(block: &str) -> bool {
+    // code blocks in block quotes keep their `>` markers
+    let lines: Vec<&str> = block
+        .lines()
+        .map(|line| line.trim_start_matches(['>', ' ']).trim_end())
+        .collect();
+
+    let (Some(open), Some(close)) = (lines.first(), lines.last()) else {
+        return false;
+    };
+    let Some(fence) = open.chars().next() else {
+        return false;
+    };
+    let length = open.chars().take_while(|c| *c == fence).count();
+
+    lines.len() > 1 && close.len() >= length && close.chars().all(|c| c == fence)
+}
+
+/// The line of the byte offset, starting from 1
+fn line_of(markdown: &str, offset: usize) -> usize {
+    markdown[..offset].matches('\n').count() + 1
+}
+
+/// The anchors github generates for headings, a heading with the same anchor
+/// as a previous one gets a `-1`, `-2`... suffix
+#[derive(Default)]
+struct Anchors {
+    seen: HashMap<String, usize>,
+}
+
+impl Anchors {
+    fn next(&mut self, title: &str) -> String {
+        let anchor = anchor(title);
+        let count = self.seen.entry(anchor.clone()).or_default();
+        *count += 1;
+
+        match *count {
+            1 => anchor,
+            n => format!("{}-{}", anchor, n - 1),
+        }
+    }
+}
+
+/// The github anchor of a heading, lowercase letters, digits, `-` and `_`,
+/// with spaces replaced by `-`
+fn anchor(title: &str) -> String {
+    title
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The author name and profile link of an attribution paragraph, such as
+/// `Tip given by [@someone](https://twitter.com/someone/status/1)`
+fn attribution(paragraph: &str) -> Option<(String, String)> {
+    let paragraph = paragraph.trim();
+    let prefix = paragraph.get(..ATTRIBUTION_PREFIX.len())?;
+    if !prefix.eq_ignore_ascii_case(ATTRIBUTION_PREFIX) {
+        return None;
+    }
+
+    let rest = paragraph[ATTRIBUTION_PREFIX.len()..].trim();
+    let link = rest
+        .strip_prefix('[')
+        .and_then(|r| r.split_once("]("))
+        .and_then(|(name, r)| r.split_once(')').map(|(url, _)| (name, url)));
+
+    match link {
+        Some((name, url)) => Some((name.trim().to_string(), profile_link(url.trim()))),
+        None => Some((rest.trim_end_matches('.').to_string(), String::new())),
+    }
+}
+
+/// Tips often credit a tweet, link to the profile of its author instead
+fn profile_link(url: &str) -> String {
+    match url.find("/status/") {
+        Some(i) if url.contains("twitter.com/") || url.contains("x.com/") => url[..i].to_string(),
+        _ => url.to_string(),
+    }
+}
+
+/// A paragraph of navigation links, such as `⬆️ [Go to main menu](README.md#laravel-tips)`
+/// or `[⬆️ Back to top](#laravel-tips)`
+fn is_navigation(paragraph: &str) -> bool {
+    let paragraph = paragraph.trim_start().trim_start_matches('[');
+
+    paragraph.starts_with(NAVIGATION_ARROWS) || paragraph.to_lowercase().starts_with("back to top")
+}
+
+/// A list of links to headings of the file only, such as `- [Title](#title)`
+fn is_table_of_contents(list: &str) -> bool {
+    let is_entry = |line: &str| {
+        let Some(item) = line.trim().strip_prefix(['-', '*', '+']) else {
+            return false;
+        };
+
+        item.trim()
+            .strip_prefix('[')
+            .and_then(|item| item.split_once("](#"))
+            .is_some_and(|(_, rest)| rest.trim_end().ends_with(')'))
+    };
+
+    let mut lines = list
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+
+    lines.peek().is_some() && lines.all(is_entry)
+}
+
+/// The end of the line at `offset`, after its line break and one blank line
+fn line_end(markdown: &str, offset: usize) -> usize {
+    let mut end = match markdown[..offset].ends_with('\n') {
+        true => offset,
+        false => markdown[offset..]
+            .find('\n')
+            .map(|i| offset + i + 1)
+            .unwrap_or(markdown.len()),
+    };
+
+    if markdown[end..].starts_with('\n') {
+        end += 1;
+    }
+
+    end
+}
+
+/// The language of a code block, the first word of the fence info such as
+/// `php` for ```` ```php title="routes/api.php" ````
+fn language(kind: &CodeBlockKind) -> String {
+    match kind {
+        CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+        CodeBlockKind::Indented => String::new(),
+    }
+}
+
+/// The body starts on the line after the heading, the range of the heading
+/// may or may not include its line break
+fn body_start(markdown: &str, heading_end: usize) -> usize {
+    if markdown[..heading_end].ends_with('\n') {
+        return heading_end;
+    }
+
+    match markdown[heading_end..].find('\n') {
+        Some(i) if markdown[heading_end..heading_end + i].trim().is_empty() => heading_end + i + 1,
+        _ => heading_end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::base64_decode;
+
+    fn titles(tips: &[Tip]) -> Vec<&str> {
+        tips.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_api_fixture() {
+        let content = std::fs::read_to_string("testdata/api_base64.md").unwrap();
+        let tips = parse_tips(&base64_decode(content).unwrap());
+
+        assert_eq!(
+            titles(&tips),
+            vec![
+                "API Resources: With or Without \"data\"?",
+                "Conditional Relationship Counts on API Resources",
+                "API Return \"Everything went ok\"",
+                "Avoid N+1 queries in API resources",
+                "Get Bearer Token from Authorization header",
+                "Sorting Your API Results",
+                "Customize Exception Handler For API",
+                "Force JSON Response For API Requests",
+                "API Versioning",
+            ]
+        );
+
+        // subheadings stay in the body of their tip
+        let versioning = &tips[8];
+        assert!(versioning.content.contains("#### When to version?\n"));
+        assert!(versioning.content.contains("#### Route File Versioning\n"));
+        assert!(tips[6].content.contains("#### Laravel 9 and above:\n"));
+
+        // the code blocks of the tip, with the language of their fences
+        let sorting = &tips[5];
+        assert_eq!(sorting.snippets.len(), 2);
+        assert!(sorting.snippets.iter().all(|s| s.language == "php"));
+        assert!(sorting.content.contains(&sorting.snippets[0].code));
+        assert_eq!(tips[7].snippets[0].language, "console");
+
+        // the attribution line is parsed into the author and removed from the body
+        let first = &tips[0];
+        assert_eq!(first.author, "@phillipmwaniki");
+        assert_eq!(first.author_link, "https://twitter.com/phillipmwaniki");
+        assert!(!first.content.contains("Tip given by"));
+        assert!(first.content.ends_with("```\n\n"));
+        assert_eq!(tips[6].author, "Feras Elsharif");
+        assert_eq!(tips[6].author_link, "https://github.com/ferasbbm");
+        assert!(tips[6].content.ends_with("```\n\n"));
+        assert!(tips.iter().all(|t| !t.content.contains("Tip given by")));
+
+        // the anchors match the links of the table of contents
+        assert_eq!(first.anchor, "api-resources-with-or-without-data");
+        assert_eq!(tips[3].anchor, "avoid-n1-queries-in-api-resources");
+    }
+
+    #[test]
+    fn test_parse_code_blocks_fixture() {
+        let content = std::fs::read_to_string("testdata/code_blocks.md").unwrap();
+        let tips = parse_tips(&content);
+
+        assert_eq!(
+            titles(&tips),
+            vec!["Run artisan in a shell", "Use `whereIn`", "C#", "Last tip"]
+        );
+
+        // the body is kept verbatim, including `###` inside code blocks
+        assert_eq!(
+            tips[0].content,
+            "\nComments in shell scripts start with `#`:\n\n```bash\n### not a tip\n$ php artisan migrate\n```\n\n"
+        );
+        assert!(tips[1].content.contains("    ### indented code\n"));
+        assert!(tips[1].content.contains("> ### quoted heading\n"));
+        assert_eq!(tips[3].content, "\nThe end.\n");
+
+        assert_eq!(
+            tips[0].snippets,
+            vec![Snippet {
+                language: "bash".to_string(),
+                code: "### not a tip\n$ php artisan migrate\n".to_string(),
+            }]
+        );
+        let languages: Vec<&str> = tips[1]
+            .snippets
+            .iter()
+            .map(|s| s.language.as_str())
+            .collect();
+        assert_eq!(languages, vec!["", "php"]);
+        assert!(tips[3].snippets.is_empty());
+    }
+
+    #[test]
+    fn test_parse_navigation_fixture() {
+        let content = std::fs::read_to_string("testdata/navigation.md").unwrap();
+        let tips = parse_tips(&content);
+
+        assert_eq!(titles(&tips), vec!["First tip", "Second tip"]);
+        assert_eq!(
+            tips.iter().map(|t| t.position).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        // lists with real links and text are kept
+        assert_eq!(
+            tips[0].content,
+            "\nUse the [docs](https://laravel.com/docs) and keep this list:\n\n\
+             - [a real link](https://laravel.com)\n\
+             - [another one](#second-tip) with text\n\n"
+        );
+        assert_eq!(tips[0].author, "@someone");
+        assert_eq!(tips[1].content, "\nThe second tip.\n\n");
+    }
+
+    #[test]
+    fn test_is_table_of_contents() {
+        assert!(is_table_of_contents(
+            "- [First](#first)\n  - [Nested](#nested)\n"
+        ));
+        assert!(!is_table_of_contents(
+            "- [Docs](https://laravel.com/docs)\n"
+        ));
+        assert!(!is_table_of_contents("- [First](#first) and more\n"));
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let content = std::fs::read_to_string("testdata/broken.md").unwrap();
+        let tips = parse_tips(&content);
+
+        assert_eq!(titles(&tips), vec!["Empty", "Fenced", "Empty"]);
+        assert_eq!(
+            tips.iter().map(|t| t.lines).collect::<Vec<_>>(),
+            vec![(1, 1), (3, 8), (10, 14)]
+        );
+
+        let warnings = diagnose(&content, &tips);
+        let lines: Vec<usize> = warnings.iter().map(|w| w.line).collect();
+        assert_eq!(lines, vec![1, 10, 12]);
+        assert!(warnings[0].message.contains("empty body"));
+        assert!(warnings[1].message.contains("first used on line 1"));
+        assert!(warnings[2].message.contains("unterminated code fence"));
+
+        let content = std::fs::read_to_string("testdata/code_blocks.md").unwrap();
+        assert!(diagnose(&content, &parse_tips(&content)).is_empty());
+    }
+
+    #[test]
+    fn test_attribution() {
+        assert_eq!(
+            attribution("Tip given by [@someone](https://x.com/someone/status/1)\n"),
+            Some(("@someone".to_string(), "https://x.com/someone".to_string()))
+        );
+        assert_eq!(
+            attribution("tip given by Taylor."),
+            Some(("Taylor".to_string(), "".to_string()))
+        );
+        assert_eq!(attribution("Tips are given by the community"), None);
+    }
+
+    #[test]
+    fn test_anchors() {
+        let mut anchors = Anchors::default();
+
+        assert_eq!(
+            anchors.next("Use `whereIn` with Arr::get"),
+            "use-wherein-with-arrget"
+        );
+        assert_eq!(
+            anchors.next("API Return \"Everything went ok\""),
+            "api-return-everything-went-ok"
+        );
+        assert_eq!(anchors.next("Laravel 8 and above:"), "laravel-8-and-above");
+        assert_eq!(
+            anchors.next("Laravel 8 and above:"),
+            "laravel-8-and-above-1"
+        );
+    }
+
+    #[test]
+    fn test_heading_title() {
+        let tips = parse_tips(
+            "###   Title ###\n\n### C#\n\n### Use `whereIn` ##\n\n### Use [Carbon](https://carbon.nesbot.com) *now*\n",
+        );
+        let titles: Vec<_> = tips
+            .iter()
+            .map(|t| (t.title.as_str(), t.anchor.as_str()))
+            .collect();
+
+        assert_eq!(
+            titles,
+            vec![
+                ("Title", "title"),
+                ("C#", "c"),
+                ("Use `whereIn`", "use-wherein"),
+                ("Use Carbon now", "use-carbon-now"),
+            ]
+        );
+    }
+}
@@ -1,14 +1,82 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Entity {
     pub id: String,
     pub title: String,
     pub content: String,
+    /// The upstream file the tip comes from, such as `eloquent` for `eloquent.md`
+    #[serde(default)]
+    pub group: String,
+    /// The name of the source the tip is synced from, such as `default`
+    #[serde(default)]
+    pub source: String,
+    /// The code blocks of the tip, in the order they appear in the content
+    #[serde(default)]
+    pub snippets: Vec<Snippet>,
+    /// The name of the author credited by the tip, such as `@someone`
+    #[serde(default)]
+    pub author: String,
+    /// The profile link of the author
+    #[serde(default)]
+    pub author_link: String,
+    /// The link to the heading of the tip in its source, empty if unknown
+    #[serde(default)]
+    pub link: String,
+    /// The position of the tip in its file, starting from 1
+    #[serde(default)]
+    pub position: usize,
+    /// The path of the file in its source, such as `eloquent.md`, tips of
+    /// unchanged files are kept by it on incremental syncs
+    #[serde(default)]
+    pub path: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Tip {
     pub title: String,
     pub content: String,
+    pub snippets: Vec<Snippet>,
+    pub author: String,
+    pub author_link: String,
+    /// The anchor of the heading, such as `eager-loading-with-exact-columns`
+    pub anchor: String,
+    /// The position of the tip in its file, starting from 1
+    pub position: usize,
+    /// The first and the last line of the tip in its file, starting from 1
+    pub lines: (usize, usize),
+}
+
+/// A fenced or indented code block of a tip
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    /// The language of the fence, such as `php` for ```` ```php ````, empty if not given
+    pub language: String,
+    pub code: String,
+}
+
+/// The state of the last sync of every source, keyed by the source name
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub sources: HashMap<String, SourceState>,
+}
+
+/// The upstream state of the last sync of a source, used to skip unchanged files
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceState {
+    /// The sha of the upstream git tree, empty if the last sync is incomplete
+    pub sha: String,
+    /// The blob sha of each synced file, keyed by the file path
+    pub files: HashMap<String, String>,
+    /// The upstream the state belongs to, the state is ignored if the upstream changes
+    #[serde(default)]
+    pub upstream: String,
+    /// The upstream commit the tips come from, empty if the source is not a repository
+    #[serde(default)]
+    pub commit: String,
+    /// The unix timestamp of the last successful sync
+    #[serde(default)]
+    pub synced_at: u64,
 }
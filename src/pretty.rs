@@ -1,5 +1,8 @@
 use crate::model::Entity;
+use crate::storage::Group;
 use crate::OutputFormat;
+use console::style;
+use serde::Serialize;
 use std::io::{stdout, Write};
 use termimad::crossterm::{
     cursor::{Hide, Show},
@@ -36,6 +39,98 @@ impl Pretty {
     pub fn print_tips(&self, tips: Vec<Entity>) -> anyhow::Result<()> {
         self.printer().print(tips)
     }
+
+    /// print one line per tip with its id, group and title, without the content
+    pub fn print_titles(&self, tips: Vec<Entity>) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Title<'a> {
+            id: &'a str,
+            source: &'a str,
+            group: &'a str,
+            title: &'a str,
+        }
+
+        match self.format {
+            OutputFormat::Json => {
+                let titles: Vec<Title> = tips
+                    .iter()
+                    .map(|e| Title {
+                        id: &e.id,
+                        source: &e.source,
+                        group: &e.group,
+                        title: &e.title,
+                    })
+                    .collect();
+
+                println!("{}", serde_json::to_string(&titles)?);
+            }
+            OutputFormat::Text => {
+                for tip in tips {
+                    println!("{}\t{}\t{}", tip.id, tip.group, tip.title);
+                }
+            }
+            OutputFormat::Terminal => {
+                for tip in tips {
+                    println!(
+                        "{} {} {}",
+                        style(&tip.id).dim(),
+                        style(format!("[{}]", tip.group)).yellow(),
+                        tip.title
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// print every group with the number of its tips
+    pub fn print_groups(&self, groups: Vec<Group>) -> anyhow::Result<()> {
+        match self.format {
+            OutputFormat::Json => println!("{}", serde_json::to_string(&groups)?),
+            OutputFormat::Text => {
+                for group in groups {
+                    println!("{}\t{}", group.name, group.tips);
+                }
+            }
+            OutputFormat::Terminal => {
+                let width = groups.iter().map(|g| g.name.len()).max().unwrap_or(0);
+                for group in groups {
+                    println!(
+                        "{:width$}  {}",
+                        style(&group.name).yellow(),
+                        style(format!("{} tip(s)", group.tips)).dim(),
+                        width = width
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Render the tip as markdown, with its id so it can be shown again by [show],
+/// its author and the link to its source
+fn markdown(tip: &Entity) -> String {
+    let mut meta = Vec::new();
+    if !tip.id.is_empty() {
+        meta.push(format!("id: {}", tip.id));
+    }
+    if !tip.author.is_empty() {
+        meta.push(format!("by {}", tip.author));
+    }
+
+    let mut markdown = format!("### {}\n", tip.title);
+    if !meta.is_empty() {
+        markdown.push_str(&format!("*{}*\n", meta.join(", ")));
+    }
+    markdown.push_str(&format!("\n{}\n", tip.content.trim()));
+    if !tip.link.is_empty() {
+        markdown.push_str(&format!("\nsource: {}\n", tip.link));
+    }
+
+    markdown
 }
 
 trait Printable {
@@ -51,7 +146,7 @@ struct JsonPrinter {}
 impl Printable for TextPrinter {
     fn print(&self, tips: Vec<Entity>) -> anyhow::Result<()> {
         for tip in tips {
-            println!("### {}\n{}\n", tip.title, tip.content);
+            println!("{}", markdown(&tip));
         }
 
         Ok(())
@@ -99,7 +194,7 @@ impl Printable for TerminalPrinter {
         let size = &tips.len();
         let mut contents = tips
             .iter()
-            .map(|entity| format!("### {}\n{}\n", entity.title, entity.content))
+            .map(markdown)
             .collect::<Vec<String>>()
             .join("\n");
 
@@ -0,0 +1,553 @@
+use crate::github::HttpError;
+use crate::markdown::Warning;
+use crate::model::{Entity, SyncState, Tip};
+use crate::storage::StorageInfo;
+use crate::utils::format_timestamp;
+use crate::OutputFormat;
+use console::style;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A file or a whole source failed to download or parse during sync
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    /// The file path, empty if the whole source failed
+    pub path: String,
+    /// The http status code if the request was answered with an error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    pub error: String,
+}
+
+impl Failure {
+    pub fn new(path: &str, err: &anyhow::Error) -> Self {
+        Self {
+            path: path.to_string(),
+            status: err.downcast_ref::<HttpError>().map(|e| e.status),
+            error: err.to_string(),
+        }
+    }
+}
+
+/// The result of syncing one source
+#[derive(Debug, Default, Serialize)]
+pub struct SourceReport {
+    pub name: String,
+    pub location: String,
+    /// The number of tips of the source after sync
+    pub tips: usize,
+    /// Whether the source is not changed since the last sync
+    pub up_to_date: bool,
+    pub failures: Vec<Failure>,
+}
+
+/// A tip in the diff
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TipRef {
+    pub source: String,
+    pub group: String,
+    pub title: String,
+}
+
+impl From<&Entity> for TipRef {
+    fn from(e: &Entity) -> Self {
+        Self {
+            source: e.source.clone(),
+            group: e.group.clone(),
+            title: e.title.clone(),
+        }
+    }
+}
+
+/// The tips added, removed and changed by a sync
+#[derive(Debug, Default, Serialize)]
+pub struct Diff {
+    pub added: Vec<TipRef>,
+    pub removed: Vec<TipRef>,
+    pub changed: Vec<TipRef>,
+}
+
+impl Diff {
+    /// Compare the tips in storage with the newly synced ones, tips are matched
+    /// by their ids, which are unique even if titles repeat
+    pub fn new(old: &[Entity], new: &[Entity]) -> Self {
+        let old_map: HashMap<_, _> = old.iter().map(|e| (&e.id, &e.content)).collect();
+        let new_map: HashMap<_, _> = new.iter().map(|e| (&e.id, &e.content)).collect();
+
+        let mut diff = Diff::default();
+        for e in new {
+            match old_map.get(&e.id) {
+                None => diff.added.push(e.into()),
+                Some(content) if *content != &e.content => diff.changed.push(e.into()),
+                _ => {}
+            }
+        }
+
+        diff.removed = old
+            .iter()
+            .filter(|e| !new_map.contains_key(&e.id))
+            .map(|e| e.into())
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    fn print(&self) {
+        if self.is_empty() {
+            println!("No tips added, removed or changed");
+            return;
+        }
+
+        let sections = [
+            ("Added", "+", &self.added),
+            ("Removed", "-", &self.removed),
+            ("Changed", "~", &self.changed),
+        ];
+
+        for (name, sign, tips) in sections.into_iter().filter(|s| !s.2.is_empty()) {
+            println!("{} {} tip(s):", name, tips.len());
+            for tip in tips {
+                let line = format!("  {} [{}/{}] {}", sign, tip.source, tip.group, tip.title);
+                match sign {
+                    "+" => println!("{}", style(line).green()),
+                    "-" => println!("{}", style(line).red()),
+                    _ => println!("{}", style(line).yellow()),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    /// Whether the changes are not written to storage
+    pub dry_run: bool,
+    pub sources: Vec<SourceReport>,
+    pub diff: Diff,
+}
+
+impl SyncReport {
+    /// The number of failed files of all sources
+    pub fn failures(&self) -> usize {
+        self.sources.iter().map(|s| s.failures.len()).sum()
+    }
+
+    /// Fail the sync if any file failed, unless partial syncs are allowed
+    pub fn check(&self, allow_partial: bool) -> anyhow::Result<()> {
+        let failures = self.failures();
+        if failures > 0 && !allow_partial {
+            return Err(anyhow::anyhow!(
+                "{} file(s) failed to sync, use --allow-partial to ignore",
+                failures
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Print the report as json, or print the diff to stdout and the failures
+    /// to stderr for other formats
+    pub fn print(&self, format: &OutputFormat) -> anyhow::Result<()> {
+        if *format == OutputFormat::Json {
+            println!("{}", serde_json::to_string(self)?);
+
+            return Ok(());
+        }
+
+        for source in &self.sources {
+            for failure in &source.failures {
+                let path = if failure.path.is_empty() {
+                    source.location.as_str()
+                } else {
+                    failure.path.as_str()
+                };
+
+                error!(format!(
+                    "[{}] failed to sync {}: {}",
+                    source.name, path, failure.error
+                ));
+            }
+        }
+
+        self.diff.print();
+
+        Ok(())
+    }
+}
+
+/// A summary of the local catalogue
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub tips: usize,
+    pub groups: usize,
+    pub storage: StorageInfo,
+    /// The unix timestamp of the most recent sync of any source
+    pub synced_at: Option<u64>,
+    pub sources: Vec<SourceStats>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SourceStats {
+    pub name: String,
+    pub tips: usize,
+    /// The upstream commit the tips come from
+    pub commit: Option<String>,
+    pub synced_at: Option<u64>,
+}
+
+impl Stats {
+    pub fn new(entities: &[Entity], state: &SyncState, storage: StorageInfo) -> Self {
+        let mut groups: Vec<&str> = entities.iter().map(|e| e.group.as_str()).collect();
+        groups.sort_unstable();
+        groups.dedup();
+
+        // sources with tips, and synced sources without any tip
+        let mut names: Vec<&str> = entities
+            .iter()
+            .map(|e| e.source.as_str())
+            .chain(state.sources.keys().map(|k| k.as_str()))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let sources: Vec<SourceStats> = names
+            .into_iter()
+            .map(|name| {
+                let last = state.sources.get(name);
+
+                SourceStats {
+                    name: name.to_string(),
+                    tips: entities.iter().filter(|e| e.source == name).count(),
+                    commit: last.map(|s| s.commit.clone()).filter(|c| !c.is_empty()),
+                    synced_at: last.map(|s| s.synced_at).filter(|t| *t > 0),
+                }
+            })
+            .collect();
+
+        Self {
+            tips: entities.len(),
+            groups: groups.len(),
+            storage,
+            synced_at: sources.iter().filter_map(|s| s.synced_at).max(),
+            sources,
+        }
+    }
+
+    pub fn print(&self, format: &OutputFormat) -> anyhow::Result<()> {
+        if *format == OutputFormat::Json {
+            println!("{}", serde_json::to_string(self)?);
+
+            return Ok(());
+        }
+
+        let key = |k: &str| match format {
+            OutputFormat::Terminal => style(format!("{:<10}", k)).yellow().to_string(),
+            _ => format!("{:<10}", k),
+        };
+        let synced_at = |t: Option<u64>| t.map(format_timestamp).unwrap_or("never".to_string());
+
+        println!("{} {}", key("tips"), self.tips);
+        println!("{} {}", key("groups"), self.groups);
+        println!("{} {}", key("engine"), self.storage.engine);
+        println!("{} {}", key("path"), self.storage.path);
+        println!("{} {}", key("size"), format_size(self.storage.size));
+        println!("{} {}", key("last sync"), synced_at(self.synced_at));
+
+        for source in &self.sources {
+            let commit = match &source.commit {
+                Some(commit) => format!(", commit {}", &commit[..commit.len().min(7)]),
+                None => "".to_string(),
+            };
+
+            println!(
+                "{} {}: {} tip(s){}, synced {}",
+                key("source"),
+                source.name,
+                source.tips,
+                commit,
+                synced_at(source.synced_at)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The tips and warnings of a markdown file, to debug the importer
+#[derive(Debug, Serialize)]
+pub struct ParseReport {
+    pub file: String,
+    pub group: String,
+    /// Whether the file is base64 encoded like the responses of the github api
+    pub base64: bool,
+    pub tips: Vec<Tip>,
+    pub warnings: Vec<Warning>,
+}
+
+impl ParseReport {
+    /// Print the report as json, or print the tips to stdout and the warnings
+    /// to stderr for other formats
+    pub fn print(&self, format: &OutputFormat) -> anyhow::Result<()> {
+        if *format == OutputFormat::Json {
+            println!("{}", serde_json::to_string(self)?);
+
+            return Ok(());
+        }
+
+        let dim = |s: String| match format {
+            OutputFormat::Terminal => style(s).dim().to_string(),
+            _ => s,
+        };
+
+        println!(
+            "{}: {} tip(s) in group {}{}",
+            self.file,
+            self.tips.len(),
+            self.group,
+            if self.base64 {
+                ", decoded from base64"
+            } else {
+                ""
+            }
+        );
+
+        for tip in &self.tips {
+            println!(
+                "#{} {} {}",
+                tip.position,
+                dim(format!("lines {}-{}", tip.lines.0, tip.lines.1)),
+                tip.title
+            );
+
+            for snippet in &tip.snippets {
+                let language = match snippet.language.is_empty() {
+                    true => "plain",
+                    false => snippet.language.as_str(),
+                };
+
+                println!(
+                    "    {}",
+                    dim(format!(
+                        "code: {}, {} line(s)",
+                        language,
+                        snippet.code.lines().count()
+                    ))
+                );
+            }
+            if !tip.author.is_empty() {
+                println!("    {}", dim(format!("author: {}", tip.author)));
+            }
+        }
+
+        for warning in &self.warnings {
+            error!(format!(
+                "{}:{}: {}",
+                self.file, warning.line, warning.message
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Format bytes in a human readable way, such as `1.5 MiB`
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SourceState;
+
+    fn entity(group: &str, title: &str, content: &str) -> Entity {
+        Entity {
+            id: format!("{}/{}", group, title),
+            title: title.to_string(),
+            content: content.to_string(),
+            group: group.to_string(),
+            source: "default".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_failure() {
+        let failure = Failure::new("arr.md", &anyhow::anyhow!("invalid base64"));
+        assert_eq!(failure.status, None);
+        assert_eq!(failure.error, "invalid base64");
+
+        let http = |status| HttpError {
+            status,
+            body: "Not Found".to_string(),
+        };
+        let failure = Failure::new("arr.md", &http(404).into());
+        assert_eq!(failure.status, Some(404));
+        assert_eq!(failure.error, "http status 404, error: Not Found");
+
+        // the status is kept when the error is wrapped in the rate limit context
+        let err = anyhow::Error::new(http(403)).context("rate limit exceeded, retry in 10 minutes");
+        let failure = Failure::new("", &err);
+        assert_eq!(failure.status, Some(403));
+        assert_eq!(failure.error, "rate limit exceeded, retry in 10 minutes");
+    }
+
+    #[test]
+    fn test_sync_report() {
+        let source = |name: &str, failures: Vec<Failure>| SourceReport {
+            name: name.to_string(),
+            location: format!("acme/{}", name),
+            tips: 1,
+            failures,
+            ..Default::default()
+        };
+        let failure = |path: &str, status| Failure {
+            path: path.to_string(),
+            status,
+            error: "failed".to_string(),
+        };
+
+        let mut report = SyncReport {
+            sources: vec![source("default", vec![]), source("team", vec![])],
+            diff: Diff::new(&[], &[entity("arr", "Arr::get", "get")]),
+            ..Default::default()
+        };
+        assert_eq!(report.failures(), 0);
+        assert!(report.check(false).is_ok());
+
+        report.sources[1].failures = vec![failure("arr.md", Some(404)), failure("db.md", None)];
+        assert_eq!(report.failures(), 2);
+        assert!(report.check(true).is_ok());
+        assert_eq!(
+            report.check(false).unwrap_err().to_string(),
+            "2 file(s) failed to sync, use --allow-partial to ignore"
+        );
+
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "dry_run": false,
+                "sources": [
+                    {"name": "default", "location": "acme/default", "tips": 1, "up_to_date": false, "failures": []},
+                    {"name": "team", "location": "acme/team", "tips": 1, "up_to_date": false, "failures": [
+                        {"path": "arr.md", "status": 404, "error": "failed"},
+                        {"path": "db.md", "error": "failed"}
+                    ]}
+                ],
+                "diff": {
+                    "added": [{"source": "default", "group": "arr", "title": "Arr::get"}],
+                    "removed": [],
+                    "changed": []
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![
+            entity("arr", "Arr::get", "get"),
+            entity("arr", "Arr::wrap", "wrap"),
+            entity("eloquent", "Scopes", "scopes"),
+        ];
+        let new = vec![
+            entity("arr", "Arr::get", "get with default"),
+            entity("eloquent", "Scopes", "scopes"),
+            entity("eloquent", "Where", "where"),
+        ];
+
+        let diff = Diff::new(&old, &new);
+
+        assert_eq!(diff.added, vec![TipRef::from(&new[2])]);
+        assert_eq!(diff.removed, vec![TipRef::from(&old[1])]);
+        assert_eq!(diff.changed, vec![TipRef::from(&new[0])]);
+        assert!(Diff::new(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_diff_with_duplicate_titles() {
+        let dup = |id: &str, content: &str| Entity {
+            id: id.to_string(),
+            ..entity("eloquent", "Dup", content)
+        };
+        let old = vec![
+            dup("dup-1a2b3c4d", "first"),
+            dup("dup-1a2b3c4d-2", "second"),
+        ];
+
+        assert!(Diff::new(&old, &old).is_empty());
+
+        let new = vec![
+            dup("dup-1a2b3c4d", "first"),
+            dup("dup-1a2b3c4d-2", "changed"),
+        ];
+        let diff = Diff::new(&old, &new);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.changed, vec![TipRef::from(&new[1])]);
+
+        let diff = Diff::new(&old, &old[..1]);
+        assert!(diff.added.is_empty() && diff.changed.is_empty());
+        assert_eq!(diff.removed, vec![TipRef::from(&old[1])]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut team = entity("eloquent", "Team tip", "");
+        team.source = "team".to_string();
+        let entities = vec![
+            entity("eloquent", "Scopes", ""),
+            entity("arr", "Arr::get", ""),
+            team,
+        ];
+
+        let mut state = SyncState::default();
+        state.sources.insert(
+            "default".to_string(),
+            SourceState {
+                commit: "0123456789abcdef".to_string(),
+                synced_at: 1685608200,
+                ..Default::default()
+            },
+        );
+
+        let storage = StorageInfo {
+            engine: "sqlite".to_string(),
+            path: "/tmp/.db3".to_string(),
+            size: 1536,
+        };
+        let stats = Stats::new(&entities, &state, storage);
+
+        assert_eq!(stats.tips, 3);
+        assert_eq!(stats.groups, 2);
+        assert_eq!(stats.synced_at, Some(1685608200));
+        assert_eq!(
+            stats.sources[1],
+            SourceStats {
+                name: "team".to_string(),
+                tips: 1,
+                commit: None,
+                synced_at: None,
+            }
+        );
+        assert_eq!(stats.sources[0].commit.as_deref(), Some("0123456789abcdef"));
+
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+    }
+}
@@ -0,0 +1,206 @@
+use crate::github::{self, Changes, Http, Upstream};
+use crate::model::SourceState;
+use crate::{archive, local};
+use anyhow::anyhow;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The name of the source when no name is given
+pub const DEFAULT_SOURCE: &str = "default";
+
+/// A named source of tips, the tips of all sources are merged into one catalogue
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub name: String,
+    pub kind: SourceKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceKind {
+    /// A github repository, synced incrementally
+    Github(Upstream),
+    /// A local directory of markdown files
+    Dir(PathBuf),
+    /// A `.tar.gz` or `.zip` archive, can be a local file or an url
+    Archive(String),
+}
+
+/// The sources file, a json array such as
+///
+/// ```json
+/// [
+///     {"name": "laravel-daily", "type": "github"},
+///     {"name": "team", "type": "github", "owner": "acme", "repo": "tips", "ref": "main"},
+///     {"name": "local", "type": "dir", "path": "/srv/tips"},
+///     {"name": "mirror", "type": "archive", "path": "https://example.com/tips.tar.gz"}
+/// ]
+/// ```
+///
+/// Missing github fields fallback to the upstream from environment variables and flags.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SourceConfig {
+    Github {
+        name: String,
+        api: Option<String>,
+        owner: Option<String>,
+        repo: Option<String>,
+        #[serde(rename = "ref")]
+        reference: Option<String>,
+        include: Option<Vec<String>>,
+        exclude: Option<Vec<String>>,
+    },
+    Dir {
+        name: String,
+        path: String,
+    },
+    Archive {
+        name: String,
+        path: String,
+    },
+}
+
+impl SourceConfig {
+    fn into_source(self, defaults: &Upstream) -> Source {
+        match self {
+            SourceConfig::Github {
+                name,
+                api,
+                owner,
+                repo,
+                reference,
+                include,
+                exclude,
+            } => {
+                let defaults = defaults.clone();
+
+                Source {
+                    name,
+                    kind: SourceKind::Github(Upstream {
+                        api: api.unwrap_or(defaults.api),
+                        owner: owner.unwrap_or(defaults.owner),
+                        repo: repo.unwrap_or(defaults.repo),
+                        reference: reference.unwrap_or(defaults.reference),
+                        include: include.unwrap_or(defaults.include),
+                        exclude: exclude.unwrap_or(defaults.exclude),
+                    }),
+                }
+            }
+            SourceConfig::Dir { name, path } => Source {
+                name,
+                kind: SourceKind::Dir(PathBuf::from(path)),
+            },
+            SourceConfig::Archive { name, path } => Source {
+                name,
+                kind: SourceKind::Archive(path),
+            },
+        }
+    }
+}
+
+/// Load the sources from the sources file, `None` if the file does not exist
+pub fn load_sources(path: &Path, defaults: &Upstream) -> anyhow::Result<Option<Vec<Source>>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let json = std::fs::read_to_string(path)?;
+    let configs = serde_json::from_str::<Vec<SourceConfig>>(&json)
+        .map_err(|e| anyhow!("invalid sources file {}: {}", path.to_string_lossy(), e))?;
+
+    let sources: Vec<Source> = configs
+        .into_iter()
+        .map(|c| c.into_source(defaults))
+        .collect();
+
+    for (i, source) in sources.iter().enumerate() {
+        if sources[..i].iter().any(|s| s.name == source.name) {
+            return Err(anyhow!("duplicate source name: {}", source.name));
+        }
+    }
+
+    Ok(Some(sources))
+}
+
+impl Source {
+    /// Describe where the tips come from, such as `LaravelDaily/laravel-tips`
+    pub fn location(&self) -> String {
+        match &self.kind {
+            SourceKind::Github(upstream) => upstream.name(),
+            SourceKind::Dir(dir) => dir.to_string_lossy().to_string(),
+            SourceKind::Archive(src) => src.clone(),
+        }
+    }
+
+    /// Fetch the tips changed since the `last` sync, `None` if nothing changed
+    ///
+    /// Only github sources are synced incrementally, the others always return
+    /// all of their tips.
+    pub async fn fetch(
+        &self,
+        http: &Http,
+        last: &SourceState,
+        quiet: bool,
+    ) -> anyhow::Result<Option<Changes>> {
+        if !quiet {
+            log!(format!(
+                "Start sync [{}] laravel tips from {}",
+                self.name,
+                self.location()
+            ));
+        }
+
+        let (entities, failures) = match &self.kind {
+            SourceKind::Github(upstream) => {
+                return github::parse_all_laravel_tips(http, upstream, last, quiet).await
+            }
+            SourceKind::Dir(dir) => local::parse_all_local_tips(dir, quiet)?,
+            SourceKind::Archive(src) => {
+                let bytes = archive::load_archive(http, src).await?;
+                archive::parse_archive_tips(&bytes, quiet)?
+            }
+        };
+
+        Ok(Some(Changes {
+            entities,
+            failures,
+            ..Default::default()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_load_sources() {
+        let dir = TestDir::new("sources");
+        let path = dir.join("sources.json");
+        assert!(load_sources(&path, &Upstream::default()).unwrap().is_none());
+
+        std::fs::write(
+            &path,
+            r#"[
+                {"name": "laravel-daily", "type": "github"},
+                {"name": "team", "type": "github", "owner": "acme", "repo": "tips", "ref": "main"},
+                {"name": "local", "type": "dir", "path": "/srv/tips"}
+            ]"#,
+        )
+        .unwrap();
+
+        let sources = load_sources(&path, &Upstream::default()).unwrap().unwrap();
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources[0].kind, SourceKind::Github(Upstream::default()));
+        assert_eq!(sources[1].location(), "acme/tips");
+        assert_eq!(sources[2].kind, SourceKind::Dir(PathBuf::from("/srv/tips")));
+
+        std::fs::write(
+            &path,
+            r#"[{"name": "a", "type": "dir", "path": "a"}, {"name": "a", "type": "dir", "path": "b"}]"#,
+        )
+        .unwrap();
+        assert!(load_sources(&path, &Upstream::default()).is_err());
+    }
+}
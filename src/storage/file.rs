@@ -1,10 +1,11 @@
-use crate::model::Entity;
-use crate::storage::Storage;
-use crate::utils::normalize_path;
+use crate::model::{Entity, SyncState};
+use crate::storage::{Filter, Group, Lock, Page, Sort, Storage, StorageInfo, TITLE_WEIGHT};
+use crate::utils::{normalize_path, write_atomic};
 use anyhow::anyhow;
 use async_trait::async_trait;
 use rand::prelude::SliceRandom;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 pub struct FileStorage {
     path: Option<PathBuf>,
@@ -24,19 +25,13 @@ impl FileStorage {
 
         normalize_path(suffix, self.path.clone())
     }
-}
-
-#[async_trait]
-impl Storage for FileStorage {
-    async fn store(&self, entities: Vec<Entity>) -> anyhow::Result<()> {
-        let json = serde_json::to_string(&entities)?;
 
-        std::fs::write(self.path()?, json)?;
-
-        Ok(())
+    /// The sync state is stored next to the tips file, such as `tips.json.state`
+    fn state_path(&self) -> anyhow::Result<String> {
+        Ok(format!("{}.state", self.path()?))
     }
 
-    async fn random(&self) -> anyhow::Result<Option<Entity>> {
+    fn load(&self) -> anyhow::Result<Vec<Entity>> {
         let path = self.path()?;
         let m = std::fs::metadata(&path);
 
@@ -45,60 +40,376 @@ impl Storage for FileStorage {
         }
 
         let json = std::fs::read_to_string(&path)?;
-        let entities = serde_json::from_str::<Vec<Entity>>(&json)?;
+
+        Ok(serde_json::from_str::<Vec<Entity>>(&json)?)
+    }
+}
+
+#[async_trait]
+impl Storage for FileStorage {
+    async fn store(&self, entities: Vec<Entity>) -> anyhow::Result<()> {
+        let json = serde_json::to_string(&entities)?;
+
+        write_atomic(&self.path()?, json.as_bytes())?;
+
+        Ok(())
+    }
+
+    async fn random(&self, filter: &Filter) -> anyhow::Result<Option<Entity>> {
+        let entities: Vec<Entity> = self
+            .load()?
+            .into_iter()
+            .filter(|e| filter.matches(e))
+            .collect();
 
         if entities.is_empty() {
             return Ok(None);
         }
 
         let mut rng = rand::thread_rng();
-        let entity = entities.choose(&mut rng).unwrap();
 
-        Ok(Some(Entity {
-            id: entity.id.clone(),
-            title: entity.title.clone(),
-            content: entity.content.clone(),
-        }))
+        Ok(entities.choose(&mut rng).cloned())
     }
 
-    async fn search(&self, _: &str, _: Option<&str>) -> anyhow::Result<Vec<Entity>> {
-        Err(anyhow!(
-            "file storage does not support search, please use sqlite storage"
-        ))
+    async fn search(&self, keyword: &str, filter: &Filter) -> anyhow::Result<Vec<Entity>> {
+        let entities = self.load()?;
+
+        search_entities(entities, keyword, filter)
     }
 
-    async fn flush(&self) -> anyhow::Result<()> {
-        let path = self.path()?;
-        let m = std::fs::metadata(&path);
+    async fn get(&self, id: &str) -> anyhow::Result<Option<Entity>> {
+        Ok(self.load()?.into_iter().find(|e| e.id == id))
+    }
 
-        // cannot flush if file not exists or something wrong
-        if m.is_err() || !m.unwrap().is_file() {
-            return Ok(());
+    async fn list(&self, filter: &Filter, sort: &Sort, page: &Page) -> anyhow::Result<Vec<Entity>> {
+        let mut entities: Vec<Entity> = self
+            .load()?
+            .into_iter()
+            .filter(|e| filter.matches(e))
+            .collect();
+
+        sort.sort(&mut entities);
+
+        Ok(entities
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit)
+            .collect())
+    }
+
+    async fn groups(&self, filter: &Filter) -> anyhow::Result<Vec<Group>> {
+        let mut groups: BTreeMap<String, usize> = BTreeMap::new();
+        for entity in self.load()?.iter().filter(|e| filter.matches(e)) {
+            *groups.entry(entity.group.clone()).or_default() += 1;
         }
 
-        if let Err(err) = std::fs::remove_file(&path) {
-            return Err(anyhow!("remove file failed: {}", err));
+        Ok(groups
+            .into_iter()
+            .map(|(name, tips)| Group { name, tips })
+            .collect())
+    }
+
+    async fn all(&self) -> anyhow::Result<Vec<Entity>> {
+        match self.load() {
+            Ok(entities) => Ok(entities),
+            Err(_) if !Path::new(&self.path()?).exists() => Ok(vec![]),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn state(&self) -> anyhow::Result<SyncState> {
+        let path = self.state_path()?;
+        if !Path::new(&path).is_file() {
+            return Ok(SyncState::default());
         }
 
+        let json = std::fs::read_to_string(&path)?;
+
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    async fn save_state(&self, state: SyncState) -> anyhow::Result<()> {
+        let json = serde_json::to_string(&state)?;
+
+        write_atomic(&self.state_path()?, json.as_bytes())?;
+
         Ok(())
     }
+
+    fn lock(&self) -> anyhow::Result<Lock> {
+        Lock::acquire(&format!("{}.lock", self.path()?))
+    }
+
+    fn info(&self) -> anyhow::Result<StorageInfo> {
+        let path = self.path()?;
+        let files = [path.clone(), self.state_path()?];
+
+        Ok(StorageInfo::new("file", path, &files))
+    }
+}
+
+/// Case insensitive search over title and content
+///
+/// The keyword is parsed by [`parse_query`], terms match as substrings so a
+/// trailing `*` is ignored. Results are ordered by the number of times the terms
+/// occur, a term in the title counts `TITLE_WEIGHT` times as much as a term in
+/// the content. Unlike the bm25 rank of the SQLite engine the length of the tip
+/// is not taken into account, so long tips may rank higher than they would there.
+fn search_entities(
+    entities: Vec<Entity>,
+    keyword: &str,
+    filter: &Filter,
+) -> anyhow::Result<Vec<Entity>> {
+    let query = parse_query(keyword)?;
+    if query.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut scored: Vec<(f64, Entity)> = entities
+        .into_iter()
+        .filter(|e| filter.matches(e))
+        .filter_map(|e| {
+            let title = e.title.to_lowercase();
+            let content = e.content.to_lowercase();
+
+            let score = query
+                .iter()
+                .filter_map(|terms| terms.score(&title, &content))
+                .reduce(f64::max)?;
+
+            Some((score, e))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    Ok(scored.into_iter().map(|(_, e)| e).collect())
+}
+
+/// The terms of one alternative of a query, all the included terms and none of
+/// the excluded terms must occur in a tip
+#[derive(Debug, Default, PartialEq)]
+struct Terms {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Terms {
+    /// The weighted number of occurrences of the included terms, `None` if the
+    /// lowercase title and content don't match
+    fn score(&self, title: &str, content: &str) -> Option<f64> {
+        if self
+            .exclude
+            .iter()
+            .any(|t| title.contains(t.as_str()) || content.contains(t.as_str()))
+        {
+            return None;
+        }
+
+        let mut score = 0.0;
+        for term in &self.include {
+            let in_title = title.matches(term.as_str()).count() as f64;
+            let in_content = content.matches(term.as_str()).count() as f64;
+            if in_title + in_content == 0.0 {
+                return None;
+            }
+
+            score += in_title * TITLE_WEIGHT + in_content;
+        }
+
+        Some(score)
+    }
+}
+
+enum Token {
+    Term(String),
+    And,
+    Or,
+    Not,
+}
+
+/// Parse the keyword into alternatives, a tip matches if it matches any of them
+///
+/// The boolean operators follow FTS5, `OR` separates alternatives and `NOT`
+/// excludes the next term, and `AND` is implied between terms, all in upper
+/// case. A quoted phrase is one term. Like the SQLite engine, a keyword with misplaced operators
+/// is searched as a plain phrase. Grouping with parentheses is not supported.
+fn parse_query(keyword: &str) -> anyhow::Result<Vec<Terms>> {
+    let mut alternatives = vec![Terms::default()];
+    let mut negate = false;
+    // the last token is an operator still waiting for its right term
+    let mut pending = false;
+
+    for token in tokenize(keyword)? {
+        let terms = alternatives.last_mut().expect("at least one alternative");
+        let operand = !pending && !terms.include.is_empty();
+        match token {
+            Token::Term(term) => {
+                let term = term.trim().trim_end_matches('*').to_lowercase();
+                if term.is_empty() {
+                    continue;
+                }
+
+                pending = false;
+                match std::mem::take(&mut negate) {
+                    true => terms.exclude.push(term),
+                    false => terms.include.push(term),
+                }
+            }
+            Token::And if operand => pending = true,
+            Token::Or if operand => {
+                pending = true;
+                alternatives.push(Terms::default());
+            }
+            Token::Not if operand => {
+                pending = true;
+                negate = true;
+            }
+            _ => return Ok(vec![phrase(keyword)]),
+        }
+    }
+
+    if alternatives == [Terms::default()] {
+        return Ok(vec![]);
+    }
+    if pending || alternatives.iter().any(|t| t.include.is_empty()) {
+        return Ok(vec![phrase(keyword)]);
+    }
+
+    Ok(alternatives)
+}
+
+fn tokenize(keyword: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    for (i, part) in keyword.split('"').enumerate() {
+        // inside quotes
+        if i % 2 == 1 {
+            tokens.push(Token::Term(part.to_string()));
+            continue;
+        }
+
+        if part.contains(['(', ')']) {
+            return Err(anyhow!(
+                "the file engine does not support grouping with parentheses, use the sqlite engine"
+            ));
+        }
+
+        tokens.extend(part.split_whitespace().map(|word| match word {
+            "AND" => Token::And,
+            "OR" => Token::Or,
+            "NOT" => Token::Not,
+            _ => Token::Term(word.to_string()),
+        }));
+    }
+
+    Ok(tokens)
+}
+
+/// Search the whole keyword as one phrase
+fn phrase(keyword: &str) -> Terms {
+    Terms {
+        include: vec![keyword.replace('"', "").trim().to_lowercase()],
+        ..Default::default()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::TestDir;
+
+    impl FileStorage {
+        /// Remove all tips and the sync state
+        async fn flush(&self) -> anyhow::Result<()> {
+            for path in [self.path()?, self.state_path()?] {
+                let m = std::fs::metadata(&path);
+
+                // cannot flush if file not exists or something wrong
+                if m.is_err() || !m.unwrap().is_file() {
+                    continue;
+                }
+
+                if let Err(err) = std::fs::remove_file(&path) {
+                    return Err(anyhow!("remove file failed: {}", err));
+                }
+            }
+
+            Ok(())
+        }
+    }
 
     #[tokio::test]
     async fn test_search() {
-        let storage = FileStorage::new(None, None);
-        let result = storage.search("test", None).await;
+        let dir = TestDir::new("file-search");
+        let storage = FileStorage::new(Some(dir.to_path_buf()), None);
+        storage.flush().await.expect("flush failed");
+        assert!(storage.search("test", &Filter::default()).await.is_err());
+
+        let entity = |title: &str, content: &str, group: &str| Entity {
+            title: title.to_string(),
+            content: content.to_string(),
+            group: group.to_string(),
+            source: "default".to_string(),
+            ..Default::default()
+        };
+
+        storage
+            .store(vec![
+                entity("Model events", "you can SCOPE the query", "eloquent"),
+                entity("Local scopes", "reuse the query constraints", "eloquent"),
+                entity("Arr::get", "get a value with a default", "arr"),
+                entity("Tap", "call this and that", "helpers"),
+            ])
+            .await
+            .expect("store failed");
+
+        let scopes = storage.search("scope*", &Filter::default()).await.unwrap();
+        assert_eq!(scopes.len(), 2);
+        assert_eq!(scopes[0].title, "Local scopes");
+
+        let phrase = storage
+            .search("\"the query\" reuse", &Filter::default())
+            .await;
+        assert_eq!(phrase.unwrap().len(), 1);
+
+        let either = storage
+            .search("constraints OR default", &Filter::default())
+            .await;
+        assert_eq!(either.unwrap().len(), 2);
+
+        let and = storage.search("and", &Filter::default()).await.unwrap();
+        assert_eq!(and.len(), 1);
+        assert_eq!(and[0].title, "Tap");
+
+        let without = storage.search("query NOT reuse", &Filter::default()).await;
+        let without = without.unwrap();
+        assert_eq!(without.len(), 1);
+        assert_eq!(without[0].title, "Model events");
+
+        let filter = Filter {
+            group: Some("arr".to_string()),
+            ..Default::default()
+        };
+        assert!(storage.search("scope", &filter).await.unwrap().is_empty());
+        assert_eq!(storage.search("ARR::GET", &filter).await.unwrap().len(), 1);
+
+        storage
+            .store(vec![Entity {
+                id: "arr-get-1a2b3c4d".to_string(),
+                ..entity("Arr::get", "get a value with a default", "arr")
+            }])
+            .await
+            .expect("store failed");
+        assert!(storage.get("arr-get-1a2b3c4d").await.unwrap().is_some());
+        assert!(storage.get("unknown").await.unwrap().is_none());
 
-        assert!(result.is_err());
+        storage.flush().await.expect("flush failed");
     }
 
     #[tokio::test]
     async fn test_store() {
-        let storage = FileStorage::new(Some(file_path()), None);
+        let dir = TestDir::new("file-store");
+        let storage = FileStorage::new(Some(dir.to_path_buf()), None);
         let result = storage.store(vec![]).await;
 
         storage.flush().await.expect("flush failed");
@@ -108,10 +419,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_random() {
-        let storage = FileStorage::new(Some(file_path()), None);
+        let dir = TestDir::new("file-random");
+        let storage = FileStorage::new(Some(dir.to_path_buf()), None);
         storage.flush().await.expect("flush failed before random");
 
-        let result = storage.random().await;
+        let result = storage.random(&Filter::default()).await;
 
         assert!(result.is_err());
         assert!(result
@@ -120,7 +432,7 @@ mod tests {
             .contains("can't load tips from"));
 
         storage.store(vec![]).await.expect("store failed");
-        let result = storage.random().await;
+        let result = storage.random(&Filter::default()).await;
 
         assert!(result.is_ok());
         assert!(result.unwrap().is_none());
@@ -129,10 +441,16 @@ mod tests {
             id: "1".to_string(),
             title: "test".to_string(),
             content: "test".to_string(),
+            group: "test".to_string(),
+            source: "default".to_string(),
+            ..Default::default()
         }];
 
         storage.store(v).await.expect("store failed");
-        let entity = storage.random().await.expect("random failed");
+        let entity = storage
+            .random(&Filter::default())
+            .await
+            .expect("random failed");
 
         assert!(entity.is_some());
         assert_eq!(entity.unwrap().id, "1");
@@ -140,11 +458,54 @@ mod tests {
         storage.flush().await.expect("flush failed");
     }
 
-    fn file_path() -> PathBuf {
-        std::env::current_dir().unwrap().join("testdata")
+    #[test]
+    fn test_parse_query() {
+        let terms = |include: &[&str], exclude: &[&str]| Terms {
+            include: include.iter().map(|t| t.to_string()).collect(),
+            exclude: exclude.iter().map(|t| t.to_string()).collect(),
+        };
+
+        assert_eq!(
+            parse_query("Scope* AND \"the query\"").unwrap(),
+            vec![terms(&["scope", "the query"], &[])]
+        );
+        // only a bare upper case `AND` is an operator
+        assert_eq!(
+            parse_query("this and \"AND\" that").unwrap(),
+            vec![terms(&["this", "and", "and", "that"], &[])]
+        );
+        assert_eq!(
+            parse_query("scope OR arr NOT get").unwrap(),
+            vec![terms(&["scope"], &[]), terms(&["arr"], &["get"])]
+        );
+        // operators are upper case and never quoted
+        assert_eq!(
+            parse_query("\"OR\" or not").unwrap(),
+            vec![terms(&["or", "or", "not"], &[])]
+        );
+
+        // misplaced operators search the keyword as a phrase
+        assert_eq!(
+            parse_query("NOT scope").unwrap(),
+            vec![terms(&["not scope"], &[])]
+        );
+        assert_eq!(
+            parse_query("scope OR").unwrap(),
+            vec![terms(&["scope or"], &[])]
+        );
+
+        assert_eq!(
+            parse_query("scope AND").unwrap(),
+            vec![terms(&["scope and"], &[])]
+        );
+
+        assert!(parse_query(" \"\" ").unwrap().is_empty());
+        assert!(parse_query("(scope OR arr) query").is_err());
+        assert!(parse_query("\"(scope)\"").is_ok());
     }
 
     #[test]
+    #[allow(clippy::unnecessary_literal_unwrap)]
     fn test_none_unwrap() {
         let a = None;
         let a = a.unwrap_or(1);
@@ -1,19 +1,167 @@
-use crate::model::Entity;
+use crate::model::{Entity, SyncState};
 use crate::storage::file::FileStorage;
 use crate::storage::sqlite::SqliteStorage;
 use crate::SearchEngine;
+use anyhow::anyhow;
 use async_trait::async_trait;
+use fs2::FileExt;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
 use std::path::PathBuf;
 
 mod file;
 mod sqlite;
 
+/// Weight of the title when ranking search results, the content always has
+/// weight 1.0, shared by all engines so they order results the same way.
+pub const TITLE_WEIGHT: f64 = 10.0;
+
 #[async_trait]
 pub trait Storage: Send + Sync {
     async fn store(&self, entities: Vec<Entity>) -> anyhow::Result<()>;
-    async fn random(&self) -> anyhow::Result<Option<Entity>>;
-    async fn search(&self, keyword: &str, group: Option<&str>) -> anyhow::Result<Vec<Entity>>;
-    async fn flush(&self) -> anyhow::Result<()>;
+    async fn random(&self, filter: &Filter) -> anyhow::Result<Option<Entity>>;
+    async fn search(&self, keyword: &str, filter: &Filter) -> anyhow::Result<Vec<Entity>>;
+    /// Find the tip by its id, `None` if there is no such tip
+    async fn get(&self, id: &str) -> anyhow::Result<Option<Entity>>;
+    /// One page of the tips matching the filter, in the given order
+    async fn list(&self, filter: &Filter, sort: &Sort, page: &Page) -> anyhow::Result<Vec<Entity>>;
+    /// Every group with the number of its tips matching the filter, ordered by name
+    async fn groups(&self, filter: &Filter) -> anyhow::Result<Vec<Group>>;
+    async fn all(&self) -> anyhow::Result<Vec<Entity>>;
+    async fn state(&self) -> anyhow::Result<SyncState>;
+    async fn save_state(&self, state: SyncState) -> anyhow::Result<()>;
+    /// Take the advisory lock of the storage, held while syncing so that two
+    /// concurrent syncs don't overwrite each other.
+    fn lock(&self) -> anyhow::Result<Lock>;
+    /// The engine and the files it stores the tips in
+    fn info(&self) -> anyhow::Result<StorageInfo>;
+}
+
+/// Where and how the tips are stored
+#[derive(Debug, Serialize)]
+pub struct StorageInfo {
+    pub engine: String,
+    pub path: String,
+    /// The total size of the files of the storage in bytes
+    pub size: u64,
+}
+
+impl StorageInfo {
+    /// Describe the storage, the size is the sum of the existing `files`
+    pub fn new(engine: &str, path: String, files: &[String]) -> Self {
+        let size = files
+            .iter()
+            .filter_map(|f| std::fs::metadata(f).ok())
+            .map(|m| m.len())
+            .sum();
+
+        Self {
+            engine: engine.to_string(),
+            path,
+            size,
+        }
+    }
+}
+
+/// An advisory lock on a lock file, released when dropped
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Lock the file or fail immediately if it is locked by another process
+    pub fn acquire(path: &str) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+
+        file.try_lock_exclusive()
+            .map_err(|_| anyhow!("another sync is running, lock file: {}", path))?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Narrow down the tips by group and source, both are case insensitive
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    pub group: Option<String>,
+    pub source: Option<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, entity: &Entity) -> bool {
+        let eq = |expected: &Option<String>, actual: &str| {
+            expected
+                .as_ref()
+                .is_none_or(|e| e.eq_ignore_ascii_case(actual))
+        };
+
+        eq(&self.group, &entity.group) && eq(&self.source, &entity.source)
+    }
+}
+
+/// The order of listed tips
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Sort {
+    /// The upstream order, by source, file path and the position of the tip in its file
+    #[default]
+    Position,
+    Title,
+    Group,
+    Id,
+}
+
+impl argh::FromArgValue for Sort {
+    fn from_arg_value(value: &str) -> Result<Self, String> {
+        match value {
+            "title" => Ok(Self::Title),
+            "group" => Ok(Self::Group),
+            "id" => Ok(Self::Id),
+            "position" => Ok(Self::Position),
+            _ => Err(format!(
+                "unknown sort: {}, only support [position, title, group, id]",
+                value
+            )),
+        }
+    }
+}
+
+impl Sort {
+    /// Sort the tips in place, for engines without an index
+    pub fn sort(&self, entities: &mut [Entity]) {
+        match self {
+            Sort::Position => entities.sort_by(|a, b| {
+                (&a.source, &a.path, a.position).cmp(&(&b.source, &b.path, b.position))
+            }),
+            Sort::Title => entities.sort_by_key(|e| e.title.to_lowercase()),
+            Sort::Group => entities.sort_by_key(|e| {
+                (
+                    e.group.to_lowercase(),
+                    e.source.clone(),
+                    e.path.clone(),
+                    e.position,
+                )
+            }),
+            Sort::Id => entities.sort_by(|a, b| a.id.cmp(&b.id)),
+        }
+    }
+}
+
+/// Skip `offset` tips and take at most `limit` of the rest
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub limit: usize,
+    pub offset: usize,
+}
+
+/// A group of tips, named after the file the tips come from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Group {
+    pub name: String,
+    pub tips: usize,
 }
 
 pub fn new_storage(engin: Option<SearchEngine>, path: Option<String>) -> Box<dyn Storage> {
@@ -26,3 +174,68 @@ pub fn new_storage(engin: Option<SearchEngine>, path: Option<String>) -> Box<dyn
         _ => Box::new(SqliteStorage::new(p)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_sort() {
+        let entity = |id: &str, title: &str, group: &str| Entity {
+            id: id.to_string(),
+            title: title.to_string(),
+            group: group.to_string(),
+            source: "default".to_string(),
+            path: format!("{}.md", group),
+            ..Default::default()
+        };
+        let ids = |entities: &[Entity]| entities.iter().map(|e| e.id.clone()).collect::<Vec<_>>();
+
+        let mut entities = vec![
+            entity("c", "where", "eloquent"),
+            entity("a", "Arr::get", "arr"),
+            entity("b", "accessors", "eloquent"),
+        ];
+
+        Sort::Group.sort(&mut entities);
+        assert_eq!(ids(&entities), vec!["a", "c", "b"]);
+
+        Sort::Title.sort(&mut entities);
+        assert_eq!(ids(&entities), vec!["b", "a", "c"]);
+
+        Sort::Id.sort(&mut entities);
+        assert_eq!(ids(&entities), vec!["a", "b", "c"]);
+
+        for (entity, position) in entities.iter_mut().zip([1, 2, 1]) {
+            entity.position = position;
+        }
+        Sort::Position.sort(&mut entities);
+        assert_eq!(ids(&entities), vec!["a", "c", "b"]);
+
+        // tips of files with the same stem are not interleaved
+        entities.push(Entity {
+            path: "archive/eloquent.md".to_string(),
+            position: 2,
+            ..entity("d", "old where", "eloquent")
+        });
+        Sort::Position.sort(&mut entities);
+        assert_eq!(ids(&entities), vec!["d", "a", "c", "b"]);
+
+        Sort::Group.sort(&mut entities);
+        assert_eq!(ids(&entities), vec!["a", "d", "c", "b"]);
+    }
+
+    #[test]
+    fn test_lock() {
+        let dir = TestDir::new("lock");
+        let path = dir.join("test.lock");
+        let path = path.to_string_lossy();
+
+        let lock = Lock::acquire(&path).expect("lock failed");
+        assert!(Lock::acquire(&path).is_err());
+
+        drop(lock);
+        assert!(Lock::acquire(&path).is_ok());
+    }
+}
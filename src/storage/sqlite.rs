@@ -1,22 +1,118 @@
-use crate::model::Entity;
-use crate::storage::Storage;
+use crate::model::{Entity, SyncState};
+use crate::storage::{Filter, Group, Lock, Page, Sort, Storage, StorageInfo, TITLE_WEIGHT};
 use crate::utils::normalize_path;
 use async_trait::async_trait;
-use rusqlite::{params, Connection, Row};
+use rusqlite::types::Type;
+use rusqlite::{params, CachedStatement, Connection, OptionalExtension, Row};
 use std::path::PathBuf;
+use std::time::Duration;
 
+/// Bump this whenever `SQL_CREATE_TABLE` changes, the tips table will be
+/// recreated and users need to run [sync] again.
+const SCHEMA_VERSION: i32 = 9;
+
+/// How long to wait for another connection holding the write lock
+const BUSY_TIMEOUT_SECS: u64 = 5;
+
+const SQL_DROP_TABLE: &str = r#"
+    DROP TABLE IF EXISTS laravel_tips_fts;
+    DROP TABLE IF EXISTS laravel_tips;
+    DROP TABLE IF EXISTS laravel_tips_meta;
+"#;
+
+const META_SYNC_STATE: &str = "sync_state";
+
+/// The `laravel_tips_fts` table is an external content FTS5 index of
+/// `laravel_tips`, the triggers keep it in step with every insert, update
+/// and delete on the tips table.
 const SQL_CREATE_TABLE: &str = r#"
-    ;
     CREATE TABLE IF NOT EXISTS laravel_tips (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        id TEXT PRIMARY KEY,
         title TEXT NOT NULL,
         content TEXT NOT NULL,
+        "group" TEXT NOT NULL DEFAULT '',
+        source TEXT NOT NULL DEFAULT '',
+        snippets TEXT NOT NULL DEFAULT '[]',
+        author TEXT NOT NULL DEFAULT '',
+        author_link TEXT NOT NULL DEFAULT '',
+        link TEXT NOT NULL DEFAULT '',
+        position INTEGER NOT NULL DEFAULT 0,
+        path TEXT NOT NULL DEFAULT '',
         created_at DATETIME DEFAULT CURRENT_TIMESTAMP
     );
+
+    CREATE TABLE IF NOT EXISTS laravel_tips_meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS laravel_tips_fts USING fts5(
+        title,
+        content,
+        content = 'laravel_tips',
+        tokenize = 'porter unicode61'
+    );
+
+    CREATE TRIGGER IF NOT EXISTS laravel_tips_ai AFTER INSERT ON laravel_tips BEGIN
+        INSERT INTO laravel_tips_fts (rowid, title, content)
+        VALUES (new.rowid, new.title, new.content);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS laravel_tips_ad AFTER DELETE ON laravel_tips BEGIN
+        INSERT INTO laravel_tips_fts (laravel_tips_fts, rowid, title, content)
+        VALUES ('delete', old.rowid, old.title, old.content);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS laravel_tips_au AFTER UPDATE ON laravel_tips BEGIN
+        INSERT INTO laravel_tips_fts (laravel_tips_fts, rowid, title, content)
+        VALUES ('delete', old.rowid, old.title, old.content);
+        INSERT INTO laravel_tips_fts (rowid, title, content)
+        VALUES (new.rowid, new.title, new.content);
+    END;
+"#;
+
+const SQL_SEARCH: &str = r#"
+    SELECT t.id, t.title, t.content, t."group", t.source, t.snippets,
+    t.author, t.author_link, t.link, t.position, t.path
+    FROM laravel_tips_fts f
+    JOIN laravel_tips t ON t.rowid = f.rowid
+    WHERE laravel_tips_fts MATCH ?3
+    AND (?1 IS NULL OR t."group" = ?1 COLLATE NOCASE)
+    AND (?2 IS NULL OR t.source = ?2 COLLATE NOCASE)
+    ORDER BY bm25(laravel_tips_fts, ?4, 1.0)
+"#;
+
+const SQL_RANDOM: &str = r#"
+    SELECT id, title, content, "group", source, snippets, author, author_link, link, position, path
+    FROM laravel_tips
+    WHERE (?1 IS NULL OR "group" = ?1 COLLATE NOCASE)
+    AND (?2 IS NULL OR source = ?2 COLLATE NOCASE)
+    ORDER BY RANDOM() LIMIT 1
+"#;
+
+const SQL_LIST: &str = r#"
+    SELECT id, title, content, "group", source, snippets, author, author_link, link, position, path
+    FROM laravel_tips
+    WHERE (?1 IS NULL OR "group" = ?1 COLLATE NOCASE)
+    AND (?2 IS NULL OR source = ?2 COLLATE NOCASE)
+"#;
+
+const SQL_GROUPS: &str = r#"
+    SELECT "group", COUNT(*) FROM laravel_tips
+    WHERE (?1 IS NULL OR "group" = ?1 COLLATE NOCASE)
+    AND (?2 IS NULL OR source = ?2 COLLATE NOCASE)
+    GROUP BY "group" ORDER BY "group"
+"#;
+
+const SQL_GET: &str = r#"
+    SELECT id, title, content, "group", source, snippets, author, author_link, link, position, path
+    FROM laravel_tips WHERE id = ?1
 "#;
 
 const SQL_INSERT: &str = r#"
-    INSERT INTO laravel_tips (title, content) VALUES (?, ?);
+    INSERT INTO laravel_tips
+    (id, title, content, "group", source, snippets, author, author_link, link, position, path)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
 "#;
 
 #[cfg_attr(test, derive(Debug))]
@@ -36,17 +132,29 @@ impl SqliteStorage {
     /// Open a connection to the database.
     ///
     /// @TODO if the connection is already open, reuse it.
+    ///
+    /// The database is in WAL mode, so readers keep reading the last committed
+    /// catalogue while a sync is replacing it.
     fn connection(&self) -> anyhow::Result<Connection> {
         let path = self.path()?;
 
         let con = Connection::open(path)?;
+        con.pragma_update(None, "journal_mode", "WAL")?;
+        con.busy_timeout(Duration::from_secs(BUSY_TIMEOUT_SECS))?;
 
         Ok(con)
     }
 
     fn create_table_if_not_exists(&self) -> anyhow::Result<()> {
         let con = self.connection()?;
-        con.execute(SQL_CREATE_TABLE, [])?;
+
+        let version: i32 = con.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < SCHEMA_VERSION {
+            con.execute_batch(SQL_DROP_TABLE)?;
+            con.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+
+        con.execute_batch(SQL_CREATE_TABLE)?;
 
         Ok(())
     }
@@ -54,68 +162,607 @@ impl SqliteStorage {
 
 #[async_trait]
 impl Storage for SqliteStorage {
+    /// Replace all tips in a single transaction, readers see either the old
+    /// or the new tips, and nothing changes if any insert fails.
     async fn store(&self, entities: Vec<Entity>) -> anyhow::Result<()> {
-        self.flush().await?;
+        self.create_table_if_not_exists()?;
 
-        let con = self.connection()?;
-        let mut stmt = con.prepare_cached(SQL_INSERT)?;
+        let mut con = self.connection()?;
+        let tx = con.transaction()?;
+        tx.execute("DELETE FROM laravel_tips", [])?;
+
+        {
+            let mut stmt = tx.prepare_cached(SQL_INSERT)?;
 
-        for entity in entities {
-            stmt.execute(params![entity.title, entity.content])?;
+            for entity in entities {
+                stmt.execute(params![
+                    entity.id,
+                    entity.title,
+                    entity.content,
+                    entity.group,
+                    entity.source,
+                    serde_json::to_string(&entity.snippets)?,
+                    entity.author,
+                    entity.author_link,
+                    entity.link,
+                    entity.position as i64,
+                    entity.path
+                ])?;
+            }
         }
 
+        tx.commit()?;
+
         Ok(())
     }
 
-    async fn random(&self) -> anyhow::Result<Option<Entity>> {
+    async fn random(&self, filter: &Filter) -> anyhow::Result<Option<Entity>> {
         self.create_table_if_not_exists()?;
 
         let con = self.connection()?;
-        let mut query =
-            con.prepare("SELECT id, title, content FROM laravel_tips ORDER BY RANDOM() LIMIT 1")?;
+        let mut query = con.prepare(SQL_RANDOM)?;
 
-        let entity = query.query_row([], parse_row_to_entity)?;
+        let entity = query
+            .query_row(params![filter.group, filter.source], parse_row_to_entity)
+            .optional()?;
 
-        Ok(Some(entity))
+        Ok(entity)
     }
 
-    async fn search(&self, keyword: &str, _group: Option<&str>) -> anyhow::Result<Vec<Entity>> {
+    async fn search(&self, keyword: &str, filter: &Filter) -> anyhow::Result<Vec<Entity>> {
         self.create_table_if_not_exists()?;
 
         let con = self.connection()?;
-        let mut query = con.prepare_cached(
-            "SELECT id, title, content FROM laravel_tips WHERE title LIKE ? OR content LIKE ?",
-        )?;
+        let mut query = con.prepare_cached(SQL_SEARCH)?;
 
-        let keyword = format!("%{}%", keyword);
-        let rows = query.query_map(params![keyword.clone(), keyword], parse_row_to_entity)?;
+        // the keyword is passed to FTS5 as is, so users can use phrases, prefix
+        // and boolean queries, fallback to a plain phrase if it is not valid syntax.
+        let entities = match search_entities(&mut query, filter, keyword) {
+            Err(err) if is_fts_syntax_error(&err) => {
+                search_entities(&mut query, filter, &quote_phrase(keyword))?
+            }
+            result => result?,
+        };
 
-        let mut entities = Vec::new();
-        for row in rows {
-            entities.push(row?);
-        }
+        Ok(entities)
+    }
+
+    async fn get(&self, id: &str) -> anyhow::Result<Option<Entity>> {
+        self.create_table_if_not_exists()?;
+
+        let con = self.connection()?;
+        let entity = con
+            .query_row(SQL_GET, [id], parse_row_to_entity)
+            .optional()?;
+
+        Ok(entity)
+    }
+
+    async fn list(&self, filter: &Filter, sort: &Sort, page: &Page) -> anyhow::Result<Vec<Entity>> {
+        self.create_table_if_not_exists()?;
+
+        let con = self.connection()?;
+        let sql = format!(
+            "{} ORDER BY {} LIMIT ?3 OFFSET ?4",
+            SQL_LIST,
+            order_by(sort)
+        );
+        let mut query = con.prepare(&sql)?;
+
+        let entities = query
+            .query_map(
+                params![
+                    filter.group,
+                    filter.source,
+                    page.limit as i64,
+                    page.offset as i64
+                ],
+                parse_row_to_entity,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
 
         Ok(entities)
     }
 
-    async fn flush(&self) -> anyhow::Result<()> {
+    async fn groups(&self, filter: &Filter) -> anyhow::Result<Vec<Group>> {
         self.create_table_if_not_exists()?;
 
         let con = self.connection()?;
-        con.execute("DELETE FROM laravel_tips", [])?;
+        let mut query = con.prepare(SQL_GROUPS)?;
+
+        let groups = query
+            .query_map(params![filter.group, filter.source], |row| {
+                Ok(Group {
+                    name: row.get(0)?,
+                    tips: row.get::<_, i64>(1)? as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(groups)
+    }
+
+    async fn all(&self) -> anyhow::Result<Vec<Entity>> {
+        self.create_table_if_not_exists()?;
+
+        let con = self.connection()?;
+        let mut query = con.prepare(
+            r#"
+            SELECT id, title, content, "group", source, snippets, author, author_link, link, position, path
+            FROM laravel_tips ORDER BY rowid
+            "#,
+        )?;
+
+        let entities = query
+            .query_map([], parse_row_to_entity)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(entities)
+    }
+
+    async fn state(&self) -> anyhow::Result<SyncState> {
+        self.create_table_if_not_exists()?;
+
+        let con = self.connection()?;
+        let value: Option<String> = con
+            .query_row(
+                "SELECT value FROM laravel_tips_meta WHERE key = ?",
+                [META_SYNC_STATE],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match value {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(SyncState::default()),
+        }
+    }
+
+    async fn save_state(&self, state: SyncState) -> anyhow::Result<()> {
+        self.create_table_if_not_exists()?;
+
+        let con = self.connection()?;
+        con.execute(
+            "INSERT OR REPLACE INTO laravel_tips_meta (key, value) VALUES (?, ?)",
+            params![META_SYNC_STATE, serde_json::to_string(&state)?],
+        )?;
 
         Ok(())
     }
+
+    fn lock(&self) -> anyhow::Result<Lock> {
+        Lock::acquire(&format!("{}.lock", self.path()?))
+    }
+
+    fn info(&self) -> anyhow::Result<StorageInfo> {
+        let path = self.path()?;
+        let files = [path.clone(), format!("{}-wal", path)];
+
+        Ok(StorageInfo::new("sqlite", path, &files))
+    }
 }
 
 fn parse_row_to_entity(row: &Row) -> rusqlite::Result<Entity> {
-    let id: i64 = row.get(0)?;
+    let id: String = row.get(0)?;
     let title: String = row.get(1)?;
     let content: String = row.get(2)?;
+    let group: String = row.get(3)?;
+    let source: String = row.get(4)?;
+    let snippets: String = row.get(5)?;
+    let snippets = serde_json::from_str(&snippets)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?;
 
     Ok(Entity {
-        id: id.to_string(),
+        id,
         title,
         content,
+        group,
+        source,
+        snippets,
+        author: row.get(6)?,
+        author_link: row.get(7)?,
+        link: row.get(8)?,
+        position: row.get::<_, i64>(9)? as usize,
+        path: row.get(10)?,
     })
 }
+
+/// The `ORDER BY` clause of the sort, ties keep the order the rows are inserted
+fn order_by(sort: &Sort) -> &'static str {
+    match sort {
+        Sort::Position => r#"source, path, position, rowid"#,
+        Sort::Title => "title COLLATE NOCASE, rowid",
+        Sort::Group => r#""group" COLLATE NOCASE, source, path, position, rowid"#,
+        Sort::Id => "id",
+    }
+}
+
+fn search_entities(
+    query: &mut CachedStatement,
+    filter: &Filter,
+    keyword: &str,
+) -> rusqlite::Result<Vec<Entity>> {
+    query
+        .query_map(
+            params![filter.group, filter.source, keyword, TITLE_WEIGHT],
+            parse_row_to_entity,
+        )?
+        .collect()
+}
+
+/// FTS5 reports invalid queries, such as `Arr::get` or `->`, as a generic
+/// sqlite error with a message starting with `fts5:`.
+fn is_fts_syntax_error(err: &rusqlite::Error) -> bool {
+    match err {
+        rusqlite::Error::SqliteFailure(_, Some(msg)) => {
+            msg.starts_with("fts5:") || msg.starts_with("no such column")
+        }
+        _ => false,
+    }
+}
+
+/// Quote the keyword as a FTS5 string, so it is matched as a phrase.
+fn quote_phrase(keyword: &str) -> String {
+    format!("\"{}\"", keyword.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Snippet, SourceState};
+    use crate::utils::TestDir;
+
+    fn entity(title: &str, content: &str, group: &str) -> Entity {
+        Entity {
+            id: title.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            group: group.to_string(),
+            source: "default".to_string(),
+            path: format!("{}.md", group),
+            ..Default::default()
+        }
+    }
+
+    fn group(group: &str) -> Filter {
+        Filter {
+            group: Some(group.to_string()),
+            ..Default::default()
+        }
+    }
+
+    impl SqliteStorage {
+        /// Remove all tips and the sync state, sync replaces tips with `store`
+        /// instead so readers never see an empty storage.
+        async fn flush(&self) -> anyhow::Result<()> {
+            self.create_table_if_not_exists()?;
+
+            let con = self.connection()?;
+            con.execute("DELETE FROM laravel_tips", [])?;
+            con.execute("DELETE FROM laravel_tips_meta", [])?;
+
+            Ok(())
+        }
+    }
+
+    /// A storage in a new directory, removed when the directory is dropped
+    fn storage(name: &str) -> (TestDir, SqliteStorage) {
+        let dir = TestDir::new(name);
+        let storage = SqliteStorage::new(Some(dir.to_path_buf()));
+
+        (dir, storage)
+    }
+
+    #[tokio::test]
+    async fn test_search_with_group() {
+        let (_dir, storage) = storage("sqlite-search-group");
+        storage
+            .store(vec![
+                entity("where clause", "use where in eloquent", "eloquent"),
+                entity("arr where", "filter array with Arr::where", "arr"),
+            ])
+            .await
+            .expect("store failed");
+
+        let all = storage.search("where", &Filter::default()).await.unwrap();
+        assert_eq!(all.len(), 2);
+
+        let eloquent = storage.search("where", &group("eloquent")).await.unwrap();
+        assert_eq!(eloquent.len(), 1);
+        assert_eq!(eloquent[0].group, "eloquent");
+
+        let none = storage.search("where", &group("artisan")).await.unwrap();
+        assert!(none.is_empty());
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_filter_by_source() {
+        let (_dir, storage) = storage("sqlite-filter-source");
+        let mut team = entity("Team where", "our where tip", "eloquent");
+        team.source = "team".to_string();
+
+        storage
+            .store(vec![entity("where clause", "use where", "eloquent"), team])
+            .await
+            .expect("store failed");
+
+        let filter = Filter {
+            source: Some("Team".to_string()),
+            ..Default::default()
+        };
+
+        let found = storage.search("where", &filter).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].source, "team");
+
+        let random = storage.random(&filter).await.unwrap().unwrap();
+        assert_eq!(random.title, "Team where");
+
+        let filter = Filter {
+            source: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        assert!(storage.random(&filter).await.unwrap().is_none());
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let (_dir, storage) = storage("sqlite-get");
+        storage
+            .store(vec![
+                entity("where clause", "use where in eloquent", "eloquent"),
+                entity("arr where", "filter array with Arr::where", "arr"),
+            ])
+            .await
+            .expect("store failed");
+
+        let found = storage.get("arr where").await.unwrap().unwrap();
+        assert_eq!(found.content, "filter array with Arr::where");
+        assert!(storage.get("unknown").await.unwrap().is_none());
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_list() {
+        let (_dir, storage) = storage("sqlite-list");
+        let positioned = |mut e: Entity, position| {
+            e.position = position;
+            e
+        };
+        storage
+            .store(vec![
+                positioned(entity("Accessors", "format attributes", "eloquent"), 2),
+                positioned(entity("arr where", "filter with Arr::where", "arr"), 1),
+                positioned(
+                    entity("where clause", "use where in eloquent", "eloquent"),
+                    1,
+                ),
+            ])
+            .await
+            .expect("store failed");
+
+        let page = |limit, offset| Page { limit, offset };
+        let titles = |entities: Vec<Entity>| {
+            entities
+                .into_iter()
+                .map(|e| e.title)
+                .collect::<Vec<String>>()
+        };
+
+        let all = storage
+            .list(&Filter::default(), &Sort::Position, &page(10, 0))
+            .await
+            .unwrap();
+        assert_eq!(titles(all), vec!["arr where", "where clause", "Accessors"]);
+
+        let by_title = storage
+            .list(&Filter::default(), &Sort::Title, &page(2, 1))
+            .await
+            .unwrap();
+        assert_eq!(titles(by_title), vec!["arr where", "where clause"]);
+
+        let by_group = storage
+            .list(&group("eloquent"), &Sort::Group, &page(10, 0))
+            .await
+            .unwrap();
+        assert_eq!(titles(by_group), vec!["where clause", "Accessors"]);
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_groups() {
+        let (_dir, storage) = storage("sqlite-groups");
+        storage
+            .store(vec![
+                entity("where clause", "use where in eloquent", "eloquent"),
+                entity("arr where", "filter array with Arr::where", "arr"),
+                entity("Accessors", "format attributes", "eloquent"),
+            ])
+            .await
+            .expect("store failed");
+
+        let groups = storage.groups(&Filter::default()).await.unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                Group {
+                    name: "arr".to_string(),
+                    tips: 1
+                },
+                Group {
+                    name: "eloquent".to_string(),
+                    tips: 2
+                },
+            ]
+        );
+
+        let filter = Filter {
+            source: Some("team".to_string()),
+            ..Default::default()
+        };
+        assert!(storage.groups(&filter).await.unwrap().is_empty());
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_store_snippets() {
+        let (_dir, storage) = storage("sqlite-snippets");
+        let mut tip = entity("Arr::get", "get a value", "arr");
+        tip.author = "@someone".to_string();
+        tip.link = "https://github.com/acme/tips/blob/main/arr.md#arrget".to_string();
+        tip.snippets = vec![Snippet {
+            language: "php".to_string(),
+            code: "Arr::get($array, 'a.b');\n".to_string(),
+        }];
+
+        storage.store(vec![tip]).await.expect("store failed");
+
+        let found = storage.get("Arr::get").await.unwrap().unwrap();
+        assert_eq!(found.snippets.len(), 1);
+        assert_eq!(found.snippets[0].language, "php");
+        assert_eq!(found.author, "@someone");
+        assert!(found.link.ends_with("#arrget"));
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_store_replaces_all_tips() {
+        let (_dir, storage) = storage("sqlite-store-replace");
+        storage
+            .store(vec![entity("Old", "old tip", "arr")])
+            .await
+            .expect("store failed");
+
+        storage
+            .store(vec![
+                entity("New where", "new tip", "eloquent"),
+                entity("Another", "another tip", "eloquent"),
+            ])
+            .await
+            .expect("store failed");
+
+        let all = storage.all().await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(all.iter().all(|e| e.group == "eloquent"));
+
+        // the fts index follows the replaced rows
+        assert!(storage
+            .search("old", &Filter::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            storage
+                .search("new", &Filter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_store_keeps_old_tips_if_insert_fails() {
+        let (_dir, storage) = storage("sqlite-store-rollback");
+        storage
+            .store(vec![entity("Old", "old tip", "arr")])
+            .await
+            .expect("store failed");
+
+        // the duplicate id violates the primary key halfway through the insert
+        assert!(storage
+            .store(vec![
+                entity("New", "new tip", "eloquent"),
+                entity("New", "duplicate tip", "eloquent"),
+            ])
+            .await
+            .is_err());
+
+        let all = storage.all().await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].title, "Old");
+        assert_eq!(
+            storage
+                .search("old", &Filter::default())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        storage.flush().await.expect("flush failed");
+    }
+
+    #[tokio::test]
+    async fn test_state() {
+        let (_dir, storage) = storage("sqlite-state");
+        storage.flush().await.expect("flush failed");
+        assert_eq!(storage.state().await.unwrap(), SyncState::default());
+
+        let mut source = SourceState {
+            sha: "tree".to_string(),
+            ..Default::default()
+        };
+        source
+            .files
+            .insert("arr.md".to_string(), "blob".to_string());
+
+        let mut state = SyncState::default();
+        state.sources.insert("default".to_string(), source);
+
+        storage.save_state(state.clone()).await.unwrap();
+        assert_eq!(storage.state().await.unwrap(), state);
+
+        storage.flush().await.expect("flush failed");
+        assert_eq!(storage.state().await.unwrap(), SyncState::default());
+    }
+
+    #[tokio::test]
+    async fn test_search_ranking_and_syntax() {
+        let (_dir, storage) = storage("sqlite-search-ranking");
+        storage
+            .store(vec![
+                entity("Model events", "you can scope the query", "eloquent"),
+                entity("Local scopes", "reuse the query constraints", "eloquent"),
+                entity("Arr::get", "get a value with Arr::get and a default", "arr"),
+            ])
+            .await
+            .expect("store failed");
+
+        let scopes = storage.search("scope*", &Filter::default()).await.unwrap();
+        assert_eq!(scopes.len(), 2);
+        assert_eq!(scopes[0].title, "Local scopes");
+
+        let phrase = storage
+            .search("\"the query\"", &Filter::default())
+            .await
+            .unwrap();
+        assert_eq!(phrase.len(), 2);
+
+        let not = storage
+            .search("query NOT constraints", &Filter::default())
+            .await
+            .unwrap();
+        assert_eq!(not.len(), 1);
+        assert_eq!(not[0].title, "Model events");
+
+        let invalid = storage
+            .search("Arr::get", &Filter::default())
+            .await
+            .unwrap();
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].group, "arr");
+
+        storage.flush().await.expect("flush failed");
+    }
+}
@@ -16,6 +16,8 @@ macro_rules! error {
     }};
 }
 
+/// Print a progress line to stderr, stdout is kept for the output of the
+/// command, such as the json report of `sync`
 #[macro_export]
 macro_rules! log {
     ($msg:expr) => {{
@@ -36,7 +38,7 @@ macro_rules! log {
         let emoji = emojis.choose(&mut rng).unwrap();
         let space = &$msg.starts_with(' ');
 
-        println!(
+        eprintln!(
             "{}{} {}",
             if *space { "  " } else { "" },
             style(emoji).green(),
@@ -1,8 +1,16 @@
-use crate::model::Tip;
+use crate::markdown;
+use crate::model::{Entity, Tip};
 use anyhow::anyhow;
 use base64::{engine::general_purpose, Engine};
 use home::home_dir;
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_SLUG_LEN: usize = 60;
 
 pub fn base64_decode(c: String) -> anyhow::Result<String> {
     // github api response always has a newline between each base64 part
@@ -14,40 +22,154 @@ pub fn base64_decode(c: String) -> anyhow::Result<String> {
     String::from_utf8(decoded).map_err(|e| e.into())
 }
 
+/// Parse tips from the base64 encoded file content returned by github api
 pub fn parse_tips(c: String) -> anyhow::Result<Vec<Tip>> {
-    Ok(base64_decode(c)?
-        .lines()
-        .fold((None, Vec::new()), process_line)
-        .1)
-}
-
-fn process_line(mut state: (Option<Tip>, Vec<Tip>), line: &str) -> (Option<Tip>, Vec<Tip>) {
-    match state.0.take() {
-        Some(mut entity) => {
-            if line.starts_with("### ") {
-                state.1.push(entity);
-                let title = line.trim_start_matches("###").trim().to_string();
-                state.0 = Some(Tip {
-                    title,
-                    content: String::new(),
-                });
-            } else {
-                entity.content.push_str(line);
-                entity.content.push('\n');
-                state.0 = Some(entity);
-            }
-        }
-        None => {
-            if line.starts_with("###") {
-                let title = line.trim_start_matches("###").trim().to_string();
-                state.0 = Some(Tip {
-                    title,
-                    content: String::new(),
-                });
-            }
+    Ok(markdown::parse_tips(&base64_decode(c)?))
+}
+
+/// Get the group of the tips from the file path, which is the lowercase file
+/// name without extension, such as `eloquent` for `eloquent.md`
+pub fn group_from_path(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Check if the file at the given path contains tips, which means a markdown
+/// file other than README.md and not inside a hidden directory such as `.git`
+pub fn is_tips_file(path: &str) -> bool {
+    let path = path.to_lowercase();
+    let hidden = Path::new(&path).components().any(|c| match c {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+
+    !hidden && path.ends_with(".md") && group_from_path(&path) != "readme"
+}
+
+/// Assign every tip a stable id derived from its source, file path and title,
+/// such as `eager-loading-with-exact-columns-1a2b3c4d`, so the id survives re-syncs.
+///
+/// Tips with the same title in the same file get a `-2`, `-3`... suffix in order.
+pub fn assign_ids(entities: &mut [Entity]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for entity in entities.iter_mut() {
+        let hash = Sha256::digest(
+            format!("{}/{}/{}", entity.source, entity.path, entity.title).as_bytes(),
+        );
+        let hash: String = hash[..4].iter().map(|b| format!("{:02x}", b)).collect();
+
+        let slug = slugify(&entity.title);
+        let id = match slug.is_empty() {
+            true => hash,
+            false => format!("{}-{}", slug, hash),
+        };
+
+        let count = seen.entry(id.clone()).or_default();
+        *count += 1;
+
+        entity.id = match *count {
+            1 => id,
+            n => format!("{}-{}", id, n),
+        };
+    }
+}
+
+/// Lowercase ascii letters and digits joined by `-`, at most `MAX_SLUG_LEN` chars
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
         }
     }
-    state
+
+    slug.truncate(MAX_SLUG_LEN);
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Convert the tips of the file at `path` to entities, `file_url` is the url
+/// of the file the tips link to, empty if the file has no url
+pub fn convert_tips_to_entities(tips: Vec<Tip>, path: &str, file_url: &str) -> Vec<Entity> {
+    let group = group_from_path(path);
+
+    tips.into_iter()
+        .map(|t| Entity {
+            link: match file_url.is_empty() {
+                true => String::new(),
+                false => format!("{}#{}", file_url, t.anchor),
+            },
+            id: "".to_string(),
+            title: t.title,
+            content: t.content,
+            group: group.clone(),
+            source: String::new(),
+            snippets: t.snippets,
+            author: t.author,
+            author_link: t.author_link,
+            position: t.position,
+            path: path.to_string(),
+        })
+        .collect()
+}
+
+/// Write the file atomically, the content is written to a temporary file in the
+/// same directory and renamed into place, so readers never see a partial file.
+pub fn write_atomic(path: &str, content: &[u8]) -> anyhow::Result<()> {
+    let tmp = format!("{}.{}.tmp", path, std::process::id());
+
+    let result = File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, path));
+
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(anyhow!("write {} failed: {}", path, err));
+    }
+
+    Ok(())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Format the unix timestamp as `2023-06-01 08:30:00 UTC`
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // convert days since epoch to the civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
 }
 
 pub fn normalize_path(suffix: String, path: Option<PathBuf>) -> anyhow::Result<String> {
@@ -76,6 +198,39 @@ fn create_default_laravel_directory() -> anyhow::Result<PathBuf> {
     Ok(laravel_dir)
 }
 
+/// A directory for a test, unique to the test run and removed when dropped,
+/// so runs of different versions never share their files
+#[cfg(test)]
+pub struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("laravel-tips-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create test dir failed");
+
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod test_base {
     use super::*;
@@ -98,6 +253,61 @@ mod test_base {
         assert!(x.is_ok());
     }
 
+    #[test]
+    fn test_assign_ids() {
+        let entity = |title: &str, path: &str| Entity {
+            title: title.to_string(),
+            group: group_from_path(path),
+            source: "default".to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+
+        let mut entities = vec![
+            entity("Eager Loading with Exact Columns", "eloquent.md"),
+            entity("Eager Loading with Exact Columns", "eloquent.md"),
+            entity("Eager Loading with Exact Columns", "db.md"),
+            entity("🎉", "eloquent.md"),
+            // files with the same stem are told apart by their path
+            entity("Eager Loading with Exact Columns", "Eloquent.md"),
+            entity("Eager Loading with Exact Columns", "archive/eloquent.md"),
+        ];
+        assign_ids(&mut entities);
+
+        let id = &entities[0].id;
+        assert!(id.starts_with("eager-loading-with-exact-columns-"));
+        assert_eq!(id.len(), "eager-loading-with-exact-columns-".len() + 8);
+        assert_eq!(entities[1].id, format!("{}-2", id));
+        assert_ne!(entities[2].id, *id);
+        assert_eq!(entities[3].id.len(), 8);
+        assert!(!entities[4].id.ends_with("-2") && !entities[5].id.ends_with("-2"));
+        assert_ne!(entities[4].id, entities[5].id);
+
+        // the same tips always get the same ids
+        let mut again = vec![entity("Eager Loading with Exact Columns", "eloquent.md")];
+        assign_ids(&mut again);
+        assert_eq!(again[0].id, *id);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1685608200), "2023-06-01 08:30:00 UTC");
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = TestDir::new("write-atomic");
+        let path = dir.join("tips.json").to_string_lossy().to_string();
+
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(write_atomic("/not-exists/foo.json", b"new").is_err());
+    }
+
     #[test]
     fn test_can_normalize_path() {
         let path = normalize_path(".test".to_string(), None);